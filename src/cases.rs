@@ -0,0 +1,169 @@
+//! Test cases pairing program input with its expected output.
+//!
+//! A directory of cases holds one `NAME.in` file per case, along with a
+//! `NAME.out` file containing the output expected for it. Outputs are
+//! compared line by line with whitespace normalized, so expectations may be
+//! written with or without the interpreter's decorative print prefix.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{BufferIo, Error, Hook, Program};
+
+pub struct Case {
+    pub name: String,
+    pub input: String,
+    /// `None` if the `.out` file is missing.
+    pub expected: Option<String>,
+}
+
+pub struct Outcome {
+    pub stdout: String,
+    pub result: Result<i64, Error>,
+    pub time: Duration,
+}
+
+/// Loads all cases of a directory, ordered by name.
+pub fn load_cases(dir: &Path) -> io::Result<Vec<Case>> {
+    let mut res = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|v| v.to_str()) != Some("in") {
+            continue;
+        }
+        let name = match path.file_stem().and_then(|v| v.to_str()) {
+            Some(v) => String::from(v),
+            None => continue,
+        };
+        let input = fs::read_to_string(&path)?;
+        let expected = fs::read_to_string(path.with_extension("out")).ok();
+        res.push(Case {
+            name,
+            input,
+            expected,
+        });
+    }
+    // numbered cases sort naturally when names have equal length
+    res.sort_by(|a, b| (a.name.len(), &a.name).cmp(&(b.name.len(), &b.name)));
+    Ok(res)
+}
+
+/// Runs a program on the input of a case, collecting its output.
+pub fn run_case(prog: &Program, case: &Case, hook: &mut dyn Hook) -> Outcome {
+    let mut io = BufferIo::new(&case.input);
+    let start = Instant::now();
+    let result = prog.execute_hooked(&mut io, hook);
+    Outcome {
+        stdout: io.stdout,
+        result,
+        time: start.elapsed(),
+    }
+}
+
+/// Lines of output with surrounding whitespace and trailing blank lines
+/// removed, and values separated by single spaces.
+fn normalize(output: &str) -> Vec<String> {
+    let mut res: Vec<String> = output
+        .lines()
+        .map(|v| v.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    while res.last().is_some_and(|v| v.is_empty()) {
+        res.pop();
+    }
+    res
+}
+
+pub fn same_output(actual: &str, expected: &str) -> bool {
+    normalize(actual) == normalize(expected)
+}
+
+/// Describes the first few lines differing between two outputs.
+pub fn diff(actual: &str, expected: &str) -> String {
+    const LIMIT: usize = 5;
+    let (actual, expected) = (normalize(actual), normalize(expected));
+    let mut res = String::new();
+    let mut cnt = 0;
+    for i in 0..actual.len().max(expected.len()) {
+        let (a, e) = (actual.get(i), expected.get(i));
+        if a == e {
+            continue;
+        }
+        cnt += 1;
+        if cnt > LIMIT {
+            res += "...\n";
+            break;
+        }
+        let show = |v: Option<&String>| v.map_or(String::from("nothing"), |v| format!("{:?}", v));
+        res += &format!("line {}: expected {}, got {}\n", i + 1, show(e), show(a));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_whitespace_and_trailing_lines() {
+        assert!(same_output("1  2\r\n 3\n\n\n", "1 2\n3"));
+        assert!(same_output("", "\n\n"));
+        assert!(!same_output("1 2\n", "12\n"));
+        assert!(!same_output("1\n\n2\n", "1\n2\n"));
+    }
+
+    #[test]
+    fn describes_differing_lines() {
+        assert_eq!(
+            diff("1\n2\n", "1\n3\n4\n"),
+            concat!(
+                "line 2: expected \"3\", got \"2\"\n",
+                "line 3: expected \"4\", got nothing\n",
+            )
+        );
+        assert_eq!(diff("a b\n", "a  b"), "");
+        let many = diff("1\n2\n3\n4\n5\n6\n7\n", "");
+        assert_eq!(many.lines().count(), 6);
+        assert!(many.ends_with("...\n"));
+    }
+
+    #[test]
+    fn loads_cases_in_natural_order() {
+        let dir = std::env::temp_dir().join(format!("nhotyp-cases-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, content) in [("10.in", "c"), ("2.in", "b"), ("2.out", "B"), ("1.in", "a")] {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let cases = load_cases(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = cases.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["1", "2", "10"]);
+        assert_eq!(cases[1].input, "b");
+        assert_eq!(cases[1].expected.as_deref(), Some("B"));
+        assert!(cases[0].expected.is_none());
+    }
+
+    #[test]
+    fn runs_programs_on_case_input() {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        let source = "function main as\n    let a = scan\n    let b = scan\n    let c = + a b\n    print c\n    return c\nend function\n";
+        prog.load(source).unwrap();
+        let case = Case {
+            name: String::from("sum"),
+            input: String::from("3\n4\n"),
+            expected: None,
+        };
+        let outcome = run_case(&prog, &case, &mut ());
+        assert_eq!(outcome.stdout, "7\n");
+        assert_eq!(outcome.result, Ok(7));
+        let case = Case {
+            input: String::from("3"),
+            ..case
+        };
+        let outcome = run_case(&prog, &case, &mut ());
+        assert_eq!(outcome.result, Err(Error::EndOfInput { line: 2 }));
+    }
+}
@@ -0,0 +1,250 @@
+//! Line and branch coverage, accumulated over any number of runs.
+//!
+//! Lines holding a statement or a function definition are instrumented, the
+//! latter counting calls. Every `if` has two branches, one for its condition
+//! holding and one for it failing.
+
+use std::collections::BTreeMap;
+
+use crate::{Error, Frame, Hook, Node, Program, Statement};
+
+pub struct Coverage {
+    // hits of every instrumented line
+    lines: BTreeMap<usize, u64>,
+    // functions by line of definition, with their call counts
+    funcs: BTreeMap<usize, (String, u64)>,
+    // `if` statements by line, counting conditions holding and failing
+    branches: BTreeMap<usize, [u64; 2]>,
+}
+
+fn collect_branches(node: &Node, res: &mut BTreeMap<usize, [u64; 2]>) {
+    for stmt in &node.stmts {
+        match stmt {
+            Statement::Cond { child, line, .. } => {
+                res.insert(*line, [0, 0]);
+                collect_branches(child, res);
+            }
+            Statement::Loop { child, .. } => collect_branches(child, res),
+            _ => (),
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new(prog: &Program) -> Self {
+        let mut res = Self {
+            lines: BTreeMap::new(),
+            funcs: BTreeMap::new(),
+            branches: BTreeMap::new(),
+        };
+        for (name, func) in &prog.funcs {
+            res.funcs.insert(func.line, (String::from(&name.value), 0));
+            res.lines.insert(func.line, 0);
+            for line in func.root.stmt_lines() {
+                res.lines.insert(line, 0);
+            }
+            collect_branches(&func.root, &mut res.branches);
+        }
+        res
+    }
+
+    /// Formats the coverage as a single lcov record for `path`.
+    pub fn lcov(&self, path: &str) -> String {
+        let mut res = format!("TN:\nSF:{}\n", path);
+        for (line, (name, _)) in &self.funcs {
+            res += &format!("FN:{},{}\n", line + 1, name);
+        }
+        for (name, calls) in self.funcs.values() {
+            res += &format!("FNDA:{},{}\n", calls, name);
+        }
+        let hit = self.funcs.values().filter(|v| v.1 > 0).count();
+        res += &format!("FNF:{}\nFNH:{}\n", self.funcs.len(), hit);
+        for (line, taken) in &self.branches {
+            // branches of conditions never evaluated are reported as `-`
+            for (i, cnt) in taken.iter().enumerate() {
+                let cnt = match taken.iter().sum::<u64>() {
+                    0 => String::from("-"),
+                    _ => cnt.to_string(),
+                };
+                res += &format!("BRDA:{},0,{},{}\n", line + 1, i, cnt);
+            }
+        }
+        let hit: usize = self.branches.values().flatten().filter(|v| **v > 0).count();
+        res += &format!("BRF:{}\nBRH:{}\n", self.branches.len() * 2, hit);
+        for (line, hits) in &self.lines {
+            res += &format!("DA:{},{}\n", line + 1, hits);
+        }
+        let hit = self.lines.values().filter(|v| **v > 0).count();
+        res += &format!("LF:{}\nLH:{}\nend_of_record\n", self.lines.len(), hit);
+        res
+    }
+
+    /// Formats the source with hit counts in front of instrumented lines,
+    /// marking the ones never run with `#####`.
+    pub fn annotate(&self, lines: &[String]) -> String {
+        // a final newline does not start another line
+        let lines = match lines.split_last() {
+            Some((last, rest)) if last.is_empty() => rest,
+            _ => lines,
+        };
+        let mut res = String::new();
+        for (i, source) in lines.iter().enumerate() {
+            let count = match self.lines.get(&i) {
+                Some(0) => String::from("#####"),
+                Some(v) => v.to_string(),
+                None => String::new(),
+            };
+            res += &format!("{:>9} | {}", count, source);
+            if let Some([taken, skipped]) = self.branches.get(&i) {
+                res += &format!("  (taken {}, skipped {})", taken, skipped);
+            }
+            res.push('\n');
+        }
+        let pct = |hit: usize, all: usize| match all {
+            0 => 100.0,
+            _ => hit as f64 * 100.0 / all as f64,
+        };
+        let (hit, all) = (
+            self.lines.values().filter(|v| **v > 0).count(),
+            self.lines.len(),
+        );
+        res += &format!("\nlines: {} of {} ({:.1}%)\n", hit, all, pct(hit, all));
+        let (hit, all) = (
+            self.branches.values().flatten().filter(|v| **v > 0).count(),
+            self.branches.len() * 2,
+        );
+        res += &format!("branches: {} of {} ({:.1}%)\n", hit, all, pct(hit, all));
+        res
+    }
+}
+
+impl Hook for Coverage {
+    fn on_statement(&mut self, _frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        *self.lines.entry(stmt.line()).or_default() += 1;
+        Ok(())
+    }
+
+    fn on_condition(
+        &mut self,
+        _frames: &[Frame],
+        stmt: &Statement,
+        value: i64,
+    ) -> Result<(), Error> {
+        if let Statement::Cond { line, .. } = stmt {
+            let taken = self.branches.entry(*line).or_default();
+            taken[if value != 0 { 0 } else { 1 }] += 1;
+        }
+        Ok(())
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        let line = match frames.last() {
+            Some(frame) => frame.line,
+            None => return Ok(()),
+        };
+        *self.lines.entry(line).or_default() += 1;
+        if let Some((_, calls)) = self.funcs.get_mut(&line) {
+            *calls += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{split_lines, BufferIo};
+
+    const SOURCE: &str = "function sign x as
+    let res = 0
+    if > x 0 then
+        let res = 1
+    end if
+    if < x 0 then
+        let res = -1
+    end if
+    return res
+end function
+function unused as
+    if 1 then
+        let a = 1
+    end if
+    return 0
+end function
+function main as
+    let a = scan
+    return sign a
+end function
+";
+
+    fn load() -> Program {
+        let mut prog = Program::new();
+        prog.load(SOURCE).unwrap();
+        prog
+    }
+
+    fn run(prog: &Program, coverage: &mut Coverage, input: &str) {
+        let mut io = BufferIo::new(input);
+        prog.execute_hooked(&mut io, coverage).unwrap();
+    }
+
+    #[test]
+    fn formats_lcov_records() {
+        let prog = load();
+        let mut coverage = Coverage::new(&prog);
+        run(&prog, &mut coverage, "5");
+        assert_eq!(
+            coverage.lcov("sign.nh"),
+            concat!(
+                "TN:\nSF:sign.nh\n",
+                "FN:1,sign\nFN:11,unused\nFN:17,main\n",
+                "FNDA:1,sign\nFNDA:0,unused\nFNDA:1,main\n",
+                "FNF:3\nFNH:2\n",
+                "BRDA:3,0,0,1\nBRDA:3,0,1,0\n",
+                "BRDA:6,0,0,0\nBRDA:6,0,1,1\n",
+                "BRDA:12,0,0,-\nBRDA:12,0,1,-\n",
+                "BRF:6\nBRH:2\n",
+                "DA:1,1\nDA:2,1\nDA:3,1\nDA:4,1\nDA:6,1\nDA:7,0\nDA:9,1\n",
+                "DA:11,0\nDA:12,0\nDA:13,0\nDA:15,0\n",
+                "DA:17,1\nDA:18,1\nDA:19,1\n",
+                "LF:14\nLH:9\nend_of_record\n",
+            )
+        );
+    }
+
+    #[test]
+    fn marks_lines_never_run() {
+        let prog = load();
+        let mut coverage = Coverage::new(&prog);
+        run(&prog, &mut coverage, "5");
+        let annotated = coverage.annotate(&split_lines(SOURCE));
+        let lines: Vec<_> = annotated.lines().collect();
+        assert_eq!(lines.len(), 20 + 3);
+        assert_eq!(lines[0], "        1 | function sign x as");
+        assert_eq!(
+            lines[2],
+            "        1 |     if > x 0 then  (taken 1, skipped 0)"
+        );
+        assert_eq!(lines[4], "          |     end if");
+        assert_eq!(lines[6], "    ##### |         let res = -1");
+        assert_eq!(lines[11], "    ##### |     if 1 then  (taken 0, skipped 0)");
+        assert_eq!(lines[21], "lines: 9 of 14 (64.3%)");
+        assert_eq!(lines[22], "branches: 2 of 6 (33.3%)");
+    }
+
+    #[test]
+    fn accumulates_over_runs() {
+        let prog = load();
+        let mut coverage = Coverage::new(&prog);
+        run(&prog, &mut coverage, "5");
+        run(&prog, &mut coverage, "-5");
+        let lcov = coverage.lcov("sign.nh");
+        assert!(lcov.contains("FNDA:2,sign\nFNDA:0,unused\nFNDA:2,main\n"));
+        assert!(lcov.contains("BRDA:3,0,0,1\nBRDA:3,0,1,1\nBRDA:6,0,0,1\nBRDA:6,0,1,1\n"));
+        assert!(lcov.contains("DA:2,2\n"));
+        assert!(lcov.contains("DA:7,1\n"));
+        assert!(lcov.contains("LF:14\nLH:10\n"));
+        let annotated = coverage.annotate(&split_lines(SOURCE));
+        assert!(annotated.contains("        1 |         let res = -1\n"));
+    }
+}
@@ -0,0 +1,614 @@
+//! Debug adapter speaking the Debug Adapter Protocol over stdio.
+//!
+//! The adapter runs the program on the calling thread as a `Hook`. Requests
+//! are answered up front until `configurationDone` starts execution, and
+//! afterwards whenever the program is paused. Since stdout carries the
+//! protocol, program output is forwarded as `output` events, and program
+//! input is read from a file named in the `launch` request.
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::debugger::{Breakpoint, Mode};
+use crate::json::{self, Value};
+use crate::{format_runtime_err, split_lines, Dialect, Error, Frame, Hook, Io, Program, Statement};
+
+/// The only thread ever reported to the client.
+const THREAD_ID: i64 = 1;
+
+/// Writes numbered protocol messages.
+struct Sender<'a> {
+    output: &'a mut dyn Write,
+    seq: i64,
+}
+
+impl Sender<'_> {
+    fn send(&mut self, kind: &str, mut pairs: Vec<(&str, Value)>) -> io::Result<()> {
+        let mut msg = vec![("seq", self.seq.into()), ("type", kind.into())];
+        self.seq += 1;
+        msg.append(&mut pairs);
+        json::write_message(self.output, &Value::object(msg))
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(
+            "response",
+            vec![
+                ("request_seq", request.get("seq").clone()),
+                ("success", true.into()),
+                ("command", request.get("command").clone()),
+                ("body", body),
+            ],
+        )
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(
+            "response",
+            vec![
+                ("request_seq", request.get("seq").clone()),
+                ("success", false.into()),
+                ("command", request.get("command").clone()),
+                ("message", message.into()),
+            ],
+        )
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send("event", vec![("event", event.into()), ("body", body)])
+    }
+}
+
+/// Program channels reading from a fixed input and reporting output to the
+/// client as events.
+struct EventIo<'a, 'b> {
+    input: VecDeque<String>,
+    sender: &'a RefCell<Sender<'b>>,
+}
+
+impl EventIo<'_, '_> {
+    fn output(&mut self, category: &str, s: &str) {
+        let body = Value::object(vec![("category", category.into()), ("output", s.into())]);
+        // a broken connection surfaces at the next request anyway
+        let _ = self.sender.borrow_mut().event("output", body);
+    }
+}
+
+impl Io for EventIo<'_, '_> {
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+
+    fn write_out(&mut self, s: &str) {
+        self.output("stdout", s);
+    }
+
+    fn write_err(&mut self, s: &str) {
+        self.output("stderr", s);
+    }
+}
+
+struct Adapter<'a, 'b> {
+    input: &'a mut dyn BufRead,
+    sender: &'a RefCell<Sender<'b>>,
+    path: String,
+    lines: Vec<String>,
+    // offset of the client's line numbers, usually counting from 1
+    line_base: usize,
+    // lines holding statements and names of functions, once launched
+    stmt_lines: HashSet<usize>,
+    func_names: HashSet<String>,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    entered: bool,
+    reason: &'static str,
+    disconnected: bool,
+}
+
+impl Adapter<'_, '_> {
+    fn respond(&self, request: &Value, body: Value) -> io::Result<()> {
+        self.sender.borrow_mut().respond(request, body)
+    }
+
+    fn fail(&self, request: &Value, message: &str) -> io::Result<()> {
+        self.sender.borrow_mut().fail(request, message)
+    }
+
+    fn event(&self, event: &str, body: Value) -> io::Result<()> {
+        self.sender.borrow_mut().event(event, body)
+    }
+
+    /// Loads the program named by a `launch` request, along with its input.
+    fn launch(&mut self, args: &Value) -> Result<(Program, String), String> {
+        let path = args.get("program").as_str().unwrap_or_default();
+        let content = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(_) => return Err(format!("{}: cannot read file", path)),
+        };
+        let input = match args.get("input").as_str() {
+            Some(v) => match fs::read_to_string(v) {
+                Ok(v) => v,
+                Err(_) => return Err(format!("{}: cannot read file", v)),
+            },
+            None => String::new(),
+        };
+        self.path = String::from(path);
+        self.lines = split_lines(&content);
+        let mut prog = Program::new();
+        if args.get("extended").as_bool() == Some(true) {
+            prog.set_dialect(Dialect::Extended);
+        }
+        prog.set_raw(args.get("raw").as_bool().unwrap_or(true));
+        if let Err(err) = prog.load(&content) {
+            return Err(format_runtime_err(Some(path), &self.lines, &err, 1));
+        }
+        for (name, func) in &prog.funcs {
+            self.func_names.insert(String::from(&name.value));
+            self.stmt_lines.extend(func.root.stmt_lines());
+        }
+        if args.get("stopOnEntry").as_bool() == Some(true) {
+            self.mode = Mode::Step;
+            self.reason = "entry";
+        }
+        Ok((prog, input))
+    }
+
+    /// Runs a launched program to completion, reporting how it ended.
+    fn run(&mut self, prog: &Program, input: &str) -> io::Result<()> {
+        let sender = self.sender;
+        let mut io = EventIo {
+            input: input.lines().map(String::from).collect(),
+            sender,
+        };
+        let res = prog.execute_hooked(&mut io, self);
+        if self.disconnected {
+            return Ok(());
+        }
+        let code = match res {
+            Ok(v) => v & 0xffffffff,
+            Err(err) => {
+                io.write_err(&format_runtime_err(Some(&self.path), &self.lines, &err, 1));
+                1
+            }
+        };
+        self.event("exited", Value::object(vec![("exitCode", code.into())]))?;
+        self.event("terminated", Value::object(vec![]))
+    }
+
+    /// Answers requests valid at any time. Returns false for anything else.
+    fn common(&mut self, request: &Value) -> io::Result<bool> {
+        let args = request.get("arguments");
+        match request.get("command").as_str().unwrap_or_default() {
+            "setBreakpoints" => {
+                // there is only one source, so every request replaces all
+                self.breakpoints
+                    .retain(|v| !matches!(v, Breakpoint::Line(_)));
+                let mut res = vec![];
+                for bp in args.get("breakpoints").as_array() {
+                    let line = bp.get("line").as_i64().unwrap_or_default();
+                    let idx = (line as usize).checked_sub(self.line_base);
+                    let verified = match idx {
+                        Some(v) => self.stmt_lines.is_empty() || self.stmt_lines.contains(&v),
+                        None => false,
+                    };
+                    if let (true, Some(v)) = (verified, idx) {
+                        self.breakpoints.push(Breakpoint::Line(v));
+                    }
+                    res.push(Value::object(vec![
+                        ("verified", verified.into()),
+                        ("line", line.into()),
+                    ]));
+                }
+                let body = Value::object(vec![("breakpoints", res.into())]);
+                self.respond(request, body)?;
+            }
+            "setFunctionBreakpoints" => {
+                self.breakpoints
+                    .retain(|v| !matches!(v, Breakpoint::Function(_)));
+                let mut res = vec![];
+                for bp in args.get("breakpoints").as_array() {
+                    let name = bp.get("name").as_str().unwrap_or_default();
+                    let verified = self.func_names.is_empty() || self.func_names.contains(name);
+                    if verified {
+                        self.breakpoints
+                            .push(Breakpoint::Function(String::from(name)));
+                    }
+                    res.push(Value::object(vec![("verified", verified.into())]));
+                }
+                let body = Value::object(vec![("breakpoints", res.into())]);
+                self.respond(request, body)?;
+            }
+            "setExceptionBreakpoints" => {
+                let body = Value::object(vec![("breakpoints", Value::Array(vec![]))]);
+                self.respond(request, body)?;
+            }
+            "threads" => {
+                let thread = Value::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+                let body = Value::object(vec![("threads", vec![thread].into())]);
+                self.respond(request, body)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn stack_trace(&self, frames: &[Frame]) -> Value {
+        let name = self.path.rsplit(['/', '\\']).next().unwrap_or_default();
+        let source = Value::object(vec![
+            ("name", name.into()),
+            ("path", self.path.as_str().into()),
+        ]);
+        let res: Vec<Value> = frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, frame)| {
+                Value::object(vec![
+                    ("id", (i + 1).into()),
+                    ("name", frame.name.as_str().into()),
+                    ("line", (frame.line + self.line_base).into()),
+                    ("column", 1i64.into()),
+                    ("source", source.clone()),
+                ])
+            })
+            .collect();
+        Value::object(vec![
+            ("totalFrames", res.len().into()),
+            ("stackFrames", res.into()),
+        ])
+    }
+
+    fn variables(frame: Option<&Frame>) -> Value {
+        let res: Vec<Value> = frame
+            .map(|v| v.variables())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| {
+                Value::object(vec![
+                    ("name", name.into()),
+                    ("value", value.to_string().into()),
+                    ("variablesReference", 0i64.into()),
+                ])
+            })
+            .collect();
+        Value::object(vec![("variables", res.into())])
+    }
+
+    /// Tells the client where execution stopped, then answers requests until
+    /// one of them resumes execution.
+    fn pause(&mut self, frames: &[Frame], reason: &str) -> io::Result<()> {
+        let body = Value::object(vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ]);
+        self.event("stopped", body)?;
+        self.reason = "step";
+        let depth = frames.len();
+        while let Some(request) = json::read_message(&mut *self.input)? {
+            let args = request.get("arguments");
+            // frames and variable containers both map to call depths
+            let frame = |key: &str| {
+                let id = args.get(key).as_i64().unwrap_or_default();
+                (id as usize).checked_sub(1).and_then(|v| frames.get(v))
+            };
+            self.mode = match request.get("command").as_str().unwrap_or_default() {
+                "stackTrace" => {
+                    self.respond(&request, self.stack_trace(frames))?;
+                    continue;
+                }
+                "scopes" => {
+                    let id = args.get("frameId").clone();
+                    let scope = Value::object(vec![
+                        ("name", "Locals".into()),
+                        ("variablesReference", id),
+                        ("expensive", false.into()),
+                    ]);
+                    let body = Value::object(vec![("scopes", vec![scope].into())]);
+                    self.respond(&request, body)?;
+                    continue;
+                }
+                "variables" => {
+                    let body = Self::variables(frame("variablesReference"));
+                    self.respond(&request, body)?;
+                    continue;
+                }
+                "continue" => Mode::Continue,
+                "next" => Mode::Next(depth),
+                "stepIn" => Mode::Step,
+                "stepOut" => Mode::Finish(depth),
+                "disconnect" | "terminate" => {
+                    self.respond(&request, Value::Null)?;
+                    break;
+                }
+                _ => {
+                    if !self.common(&request)? {
+                        self.fail(&request, "unsupported request")?;
+                    }
+                    continue;
+                }
+            };
+            let body = Value::object(vec![("allThreadsContinued", true.into())]);
+            return self.respond(&request, body);
+        }
+        self.disconnected = true;
+        Ok(())
+    }
+}
+
+impl Hook for Adapter<'_, '_> {
+    fn on_statement(&mut self, frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        let line = stmt.line();
+        let reason = if self.breakpoints.contains(&Breakpoint::Line(line)) {
+            "breakpoint"
+        } else if self.entered {
+            "function breakpoint"
+        } else if self.mode.stops_at(frames.len()) {
+            self.reason
+        } else {
+            return Ok(());
+        };
+        self.entered = false;
+        // a lost client leaves nobody to resume the program
+        if self.pause(frames, reason).is_err() || self.disconnected {
+            self.disconnected = true;
+            return Err(Error::Interrupted { line });
+        }
+        Ok(())
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        if let Some(frame) = frames.last() {
+            let func = Breakpoint::Function(frame.name.clone());
+            self.entered = self.breakpoints.contains(&func);
+        }
+        Ok(())
+    }
+}
+
+/// Serves a single debug session until the client disconnects.
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+    let sender = RefCell::new(Sender { output, seq: 1 });
+    let mut adapter = Adapter {
+        input,
+        sender: &sender,
+        path: String::new(),
+        lines: vec![],
+        line_base: 1,
+        stmt_lines: HashSet::new(),
+        func_names: HashSet::new(),
+        breakpoints: vec![],
+        mode: Mode::Continue,
+        entered: false,
+        reason: "breakpoint",
+        disconnected: false,
+    };
+    let mut launched = None;
+    while let Some(request) = json::read_message(&mut *adapter.input)? {
+        let args = request.get("arguments");
+        match request.get("command").as_str().unwrap_or_default() {
+            "initialize" => {
+                if args.get("linesStartAt1").as_bool() == Some(false) {
+                    adapter.line_base = 0;
+                }
+                let body = Value::object(vec![
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsFunctionBreakpoints", true.into()),
+                    ("supportsTerminateRequest", true.into()),
+                ]);
+                adapter.respond(&request, body)?;
+                adapter.event("initialized", Value::object(vec![]))?;
+            }
+            "launch" => match adapter.launch(args) {
+                Ok(v) => {
+                    launched = Some(v);
+                    adapter.respond(&request, Value::Null)?;
+                }
+                Err(message) => adapter.fail(&request, &message)?,
+            },
+            "configurationDone" => {
+                adapter.respond(&request, Value::Null)?;
+                if let Some((prog, input)) = launched.take() {
+                    adapter.run(&prog, &input)?;
+                }
+                if adapter.disconnected {
+                    return Ok(());
+                }
+            }
+            "disconnect" | "terminate" => {
+                adapter.respond(&request, Value::Null)?;
+                return Ok(());
+            }
+            _ => {
+                if !adapter.common(&request)? {
+                    adapter.fail(&request, "unsupported request")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "function sq x as
+    let y = * x x
+    return y
+end function
+function main as
+    let a = scan
+    let b = sq a
+    print b
+    return b
+end function
+";
+
+    /// Serves the given requests with the program above, reading `3` as its
+    /// input, and returns everything sent back. Sessions running at the same
+    /// time need different names.
+    fn session(name: &str, requests: &[(&str, Value)]) -> Vec<Value> {
+        let dir = std::env::temp_dir().join(format!("nhotyp-dap-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("sq.nh").to_string_lossy().into_owned();
+        let input = dir.join("sq.in").to_string_lossy().into_owned();
+        fs::write(&program, SOURCE).unwrap();
+        fs::write(&input, "3\n").unwrap();
+        let mut stream = vec![];
+        for (seq, (command, args)) in requests.iter().enumerate() {
+            let mut args = args.clone();
+            if *command == "launch" {
+                args = Value::object(vec![
+                    ("program", program.as_str().into()),
+                    ("input", input.as_str().into()),
+                ]);
+            }
+            let request = Value::object(vec![
+                ("seq", (seq + 1).into()),
+                ("type", "request".into()),
+                ("command", (*command).into()),
+                ("arguments", args),
+            ]);
+            json::write_message(&mut stream, &request).unwrap();
+        }
+        let mut output = vec![];
+        serve(&mut stream.as_slice(), &mut output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let mut output = output.as_slice();
+        let mut res = vec![];
+        while let Some(msg) = json::read_message(&mut output).unwrap() {
+            res.push(msg);
+        }
+        res
+    }
+
+    /// Names each message by its type and its command or event.
+    fn kinds(messages: &[Value]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|v| {
+                let name = v
+                    .get("command")
+                    .as_str()
+                    .or_else(|| v.get("event").as_str());
+                format!(
+                    "{} {}",
+                    v.get("type").as_str().unwrap_or_default(),
+                    name.unwrap_or_default()
+                )
+            })
+            .collect()
+    }
+
+    fn breakpoints(lines: &[i64]) -> Value {
+        let lines = lines
+            .iter()
+            .map(|v| Value::object(vec![("line", (*v).into())]))
+            .collect::<Vec<_>>();
+        Value::object(vec![("breakpoints", lines.into())])
+    }
+
+    fn empty() -> Value {
+        Value::object(vec![])
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_steps() {
+        let messages = session(
+            "lines",
+            &[
+                ("initialize", empty()),
+                ("launch", empty()),
+                ("setBreakpoints", breakpoints(&[2, 4])),
+                ("configurationDone", empty()),
+                ("stackTrace", empty()),
+                ("next", empty()),
+                ("continue", empty()),
+                ("disconnect", empty()),
+            ],
+        );
+        assert_eq!(
+            kinds(&messages),
+            [
+                "response initialize",
+                "event initialized",
+                "response launch",
+                "response setBreakpoints",
+                "response configurationDone",
+                "event stopped",
+                "response stackTrace",
+                "response next",
+                "event stopped",
+                "response continue",
+                "event output",
+                "event exited",
+                "event terminated",
+                "response disconnect",
+            ]
+        );
+        // only lines holding statements take breakpoints
+        let verified: Vec<_> = messages[3]
+            .get("body")
+            .get("breakpoints")
+            .as_array()
+            .iter()
+            .map(|v| v.get("verified").as_bool())
+            .collect();
+        assert_eq!(verified, [Some(true), Some(false)]);
+        assert_eq!(
+            messages[5].get("body").get("reason").as_str(),
+            Some("breakpoint")
+        );
+        let frames = messages[6].get("body").get("stackFrames").as_array();
+        let lines: Vec<_> = frames.iter().map(|v| v.get("line").as_i64()).collect();
+        assert_eq!(lines, [Some(2), Some(7)]);
+        assert_eq!(messages[8].get("body").get("reason").as_str(), Some("step"));
+        assert_eq!(messages[10].get("body").get("output").as_str(), Some("9\n"));
+        assert_eq!(messages[11].get("body").get("exitCode").as_i64(), Some(9));
+        for (i, msg) in messages.iter().enumerate() {
+            assert_eq!(msg.get("seq").as_i64(), Some(i as i64 + 1));
+        }
+    }
+
+    #[test]
+    fn stops_on_function_breakpoints() {
+        let function = Value::object(vec![(
+            "breakpoints",
+            vec![
+                Value::object(vec![("name", "sq".into())]),
+                Value::object(vec![("name", "cube".into())]),
+            ]
+            .into(),
+        )]);
+        let messages = session(
+            "functions",
+            &[
+                ("initialize", empty()),
+                ("launch", empty()),
+                ("setFunctionBreakpoints", function),
+                ("configurationDone", empty()),
+                ("disconnect", empty()),
+            ],
+        );
+        let verified: Vec<_> = messages[3]
+            .get("body")
+            .get("breakpoints")
+            .as_array()
+            .iter()
+            .map(|v| v.get("verified").as_bool())
+            .collect();
+        assert_eq!(verified, [Some(true), Some(false)]);
+        assert_eq!(
+            messages[5].get("body").get("reason").as_str(),
+            Some("function breakpoint")
+        );
+        // disconnecting while paused ends the session without exit events
+        assert_eq!(
+            kinds(&messages[5..]),
+            ["event stopped", "response disconnect"]
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Interactive step debugger driven by line-based commands.
+//!
+//! The debugger is a `Hook`: before every statement it decides whether to
+//! pause, and while paused it talks to the user through a console of its own.
+//! The console is separate from the program's channels, so the program may
+//! take its input from a file while commands are typed in.
+
+use crate::{Error, Frame, Hook, Io, Statement};
+
+const HELP: &str = "\
+commands:
+  s, step             run until the next statement
+  n, next             run until the next statement in this function
+  f, finish           run until this function returns
+  c, continue         run until a breakpoint is hit
+  b, break [LINE|FN]  set a breakpoint, or list all breakpoints
+  d, delete [LINE|FN] remove a breakpoint, or all of them
+  p, print [VAR...]   show variables of the current function
+  bt, backtrace       show the call stack
+  l, list             show source around the current line
+  q, quit             abort the program
+an empty line repeats the previous command
+";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+    /// Pauses before any statement on a 0-based line.
+    Line(usize),
+    /// Pauses before the first statement of a function.
+    Function(String),
+}
+
+/// How far to run before pausing again.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Continue,
+    Step,
+    // pause once the call stack is at most / less than this deep
+    Next(usize),
+    Finish(usize),
+}
+
+impl Mode {
+    /// Whether to pause before a statement with the call stack this deep.
+    pub(crate) fn stops_at(self, depth: usize) -> bool {
+        match self {
+            Self::Continue => false,
+            Self::Step => true,
+            Self::Next(v) => depth <= v,
+            Self::Finish(v) => depth < v,
+        }
+    }
+}
+
+pub struct Debugger<'a> {
+    lines: &'a [String],
+    console: &'a mut dyn Io,
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    // a function breakpoint was hit by the latest call
+    entered: bool,
+    last_command: String,
+}
+
+impl<'a> Debugger<'a> {
+    /// Creates a debugger pausing before the first statement, so that
+    /// breakpoints can be set up front.
+    pub fn new(lines: &'a [String], console: &'a mut dyn Io) -> Self {
+        Self {
+            lines,
+            console,
+            breakpoints: vec![],
+            mode: Mode::Step,
+            entered: false,
+            last_command: String::new(),
+        }
+    }
+
+    fn source(&self, line: usize) -> &str {
+        self.lines.get(line).map_or("", |v| v.as_str())
+    }
+
+    fn parse_breakpoint(&self, arg: &str) -> Option<Breakpoint> {
+        match arg.parse::<usize>() {
+            Ok(v) if v >= 1 && v <= self.lines.len() => Some(Breakpoint::Line(v - 1)),
+            Ok(_) => None,
+            Err(_) => Some(Breakpoint::Function(String::from(arg))),
+        }
+    }
+
+    fn describe(breakpoint: &Breakpoint) -> String {
+        match breakpoint {
+            Breakpoint::Line(v) => format!("line {}", v + 1),
+            Breakpoint::Function(v) => format!("function {}", v),
+        }
+    }
+
+    fn cmd_break(&mut self, args: &[&str]) {
+        if args.is_empty() {
+            let mut out = String::new();
+            for (i, bp) in self.breakpoints.iter().enumerate() {
+                out += &format!("#{} at {}\n", i + 1, Self::describe(bp));
+            }
+            if out.is_empty() {
+                out = String::from("no breakpoints\n");
+            }
+            self.console.write_out(&out);
+        }
+        for arg in args {
+            match self.parse_breakpoint(arg) {
+                Some(bp) if self.breakpoints.contains(&bp) => (),
+                Some(bp) => {
+                    let out = format!("breakpoint at {}\n", Self::describe(&bp));
+                    self.console.write_out(&out);
+                    self.breakpoints.push(bp);
+                }
+                None => self.console.write_out(&format!("no line {}\n", arg)),
+            }
+        }
+    }
+
+    fn cmd_delete(&mut self, args: &[&str]) {
+        if args.is_empty() {
+            self.breakpoints.clear();
+            self.console.write_out("all breakpoints removed\n");
+        }
+        for arg in args {
+            let bp = self.parse_breakpoint(arg);
+            match self.breakpoints.iter().position(|v| Some(v) == bp.as_ref()) {
+                Some(idx) => {
+                    self.breakpoints.remove(idx);
+                }
+                None => self
+                    .console
+                    .write_out(&format!("no breakpoint at {}\n", arg)),
+            }
+        }
+    }
+
+    fn cmd_print(&mut self, frame: &Frame, args: &[&str]) {
+        let mut out = String::new();
+        if args.is_empty() {
+            for (name, value) in frame.variables() {
+                out += &format!("{} = {}\n", name, value);
+            }
+            if out.is_empty() {
+                out = String::from("no variables in scope\n");
+            }
+        }
+        for arg in args {
+            out += &match frame.get(arg) {
+                Some(value) => format!("{} = {}\n", arg, value),
+                None => format!("no variable {} in scope\n", arg),
+            };
+        }
+        self.console.write_out(&out);
+    }
+
+    fn cmd_backtrace(&mut self, frames: &[Frame]) {
+        let mut out = String::new();
+        for (i, frame) in frames.iter().rev().enumerate() {
+            out += &format!(
+                "#{} {} at line {}: {}\n",
+                i,
+                frame.name,
+                frame.line + 1,
+                self.source(frame.line).trim()
+            );
+        }
+        self.console.write_out(&out);
+    }
+
+    fn cmd_list(&mut self, line: usize) {
+        let mut out = String::new();
+        let end = (line + 5).min(self.lines.len());
+        for i in line.saturating_sub(4)..end {
+            let marker = match i == line {
+                true => "->",
+                false => "  ",
+            };
+            out += &format!("{} {:>4} {}\n", marker, i + 1, self.source(i));
+        }
+        self.console.write_out(&out);
+    }
+
+    /// Reads and runs commands until one of them resumes execution.
+    fn pause(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        let frame = match frames.last() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let line = frame.line;
+        let out = format!(
+            "{} at line {}: {}\n",
+            frame.name,
+            line + 1,
+            self.source(line).trim()
+        );
+        self.console.write_out(&out);
+        loop {
+            self.console.write_out("(ndb) ");
+            // without a console, let the program run to completion
+            let input = match self.console.read_line() {
+                Some(v) => v,
+                None => {
+                    self.mode = Mode::Continue;
+                    self.breakpoints.clear();
+                    return Ok(());
+                }
+            };
+            if !input.trim().is_empty() {
+                self.last_command = String::from(input.trim());
+            }
+            let command = self.last_command.clone();
+            let words: Vec<&str> = command.split_whitespace().collect();
+            let (head, args) = match words.split_first() {
+                Some((head, args)) => (*head, args),
+                None => continue,
+            };
+            // commands resuming execution leave the loop
+            self.mode = match head {
+                "s" | "step" => Mode::Step,
+                "n" | "next" => Mode::Next(frames.len()),
+                "f" | "finish" => Mode::Finish(frames.len()),
+                "c" | "continue" => Mode::Continue,
+                "q" | "quit" => return Err(Error::Interrupted { line }),
+                "b" | "break" => {
+                    self.cmd_break(args);
+                    continue;
+                }
+                "d" | "delete" => {
+                    self.cmd_delete(args);
+                    continue;
+                }
+                "p" | "print" => {
+                    self.cmd_print(frame, args);
+                    continue;
+                }
+                "bt" | "backtrace" => {
+                    self.cmd_backtrace(frames);
+                    continue;
+                }
+                "l" | "list" => {
+                    self.cmd_list(line);
+                    continue;
+                }
+                "h" | "help" => {
+                    self.console.write_out(HELP);
+                    continue;
+                }
+                _ => {
+                    let out = format!("unknown command {:?}, try \"help\"\n", head);
+                    self.console.write_out(&out);
+                    continue;
+                }
+            };
+            return Ok(());
+        }
+    }
+}
+
+impl Hook for Debugger<'_> {
+    fn on_statement(&mut self, frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        let stop = self.mode.stops_at(frames.len());
+        let line = Breakpoint::Line(stmt.line());
+        if stop || self.entered || self.breakpoints.contains(&line) {
+            self.entered = false;
+            return self.pause(frames);
+        }
+        Ok(())
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        if let Some(frame) = frames.last() {
+            let func = Breakpoint::Function(frame.name.clone());
+            self.entered = self.breakpoints.contains(&func);
+        }
+        Ok(())
+    }
+
+    fn on_return(&mut self, frames: &[Frame], value: i64) -> Result<(), Error> {
+        // report the result of the function being finished
+        if self.mode == Mode::Finish(frames.len()) {
+            if let Some(frame) = frames.last() {
+                let out = format!("{} returned {}\n", frame.name, value);
+                self.console.write_out(&out);
+            }
+            self.mode = Mode::Step;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{split_lines, BufferIo, Program};
+
+    const SOURCE: &str = "function sq x as
+    let y = * x x
+    return y
+end function
+function main as
+    let a = 3
+    let b = sq a
+    print b
+    return b
+end function
+";
+
+    /// Runs the program under the debugger, returning its result and the
+    /// console transcript.
+    fn debug(commands: &str) -> (Result<i64, Error>, String) {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.load(SOURCE).unwrap();
+        let lines = split_lines(SOURCE);
+        let mut console = BufferIo::new(commands);
+        let mut debugger = Debugger::new(&lines, &mut console);
+        let res = prog.execute_hooked(&mut BufferIo::new(""), &mut debugger);
+        (res, console.stdout)
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_finishes_functions() {
+        let (res, transcript) = debug("b 2\nc\nbt\np x y\nf\np\nn\nq\n");
+        assert_eq!(res, Err(Error::Interrupted { line: 8 }));
+        assert_eq!(
+            transcript,
+            concat!(
+                "main at line 6: let a = 3\n",
+                "(ndb) breakpoint at line 2\n",
+                "(ndb) sq at line 2: let y = * x x\n",
+                "(ndb) #0 sq at line 2: let y = * x x\n",
+                "#1 main at line 7: let b = sq a\n",
+                "(ndb) x = 3\n",
+                "no variable y in scope\n",
+                "(ndb) sq returned 9\n",
+                "main at line 8: print b\n",
+                "(ndb) a = 3\n",
+                "b = 9\n",
+                "(ndb) main at line 9: return b\n",
+                "(ndb) ",
+            )
+        );
+    }
+
+    #[test]
+    fn steps_into_calls_unlike_next() {
+        let (_, transcript) = debug("n\nn\ns\n");
+        let stops: Vec<_> = transcript
+            .split("(ndb) ")
+            .filter(|v| !v.is_empty())
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                "main at line 6: let a = 3\n",
+                "main at line 7: let b = sq a\n",
+                "main at line 8: print b\n",
+                "main at line 9: return b\n",
+            ]
+        );
+        let (_, transcript) = debug("n\ns\ns\n\nq\n");
+        assert!(transcript.contains("(ndb) sq at line 2: let y = * x x\n"));
+        assert!(transcript.contains("(ndb) sq at line 3: return y\n(ndb) main at line 8"));
+    }
+
+    #[test]
+    fn breaks_on_functions_and_runs_without_console() {
+        let (res, transcript) = debug("b sq\nc\nd sq\nc\n");
+        assert_eq!(res, Ok(9));
+        assert!(transcript.ends_with("(ndb) sq at line 2: let y = * x x\n(ndb) (ndb) "));
+        // once the console is closed, the program runs to completion
+        let (res, _) = debug("");
+        assert_eq!(res, Ok(9));
+    }
+}
@@ -0,0 +1,529 @@
+//! Translation of programs into C.
+//!
+//! Values are `int64_t`, with helper functions reproducing the interpreter's
+//! arithmetic: positive results wrap around at 2^48, `%` never yields negative
+//! values, `/` rounds towards negative infinity and dividing by 0 yields 0.
+//! Negative values are never wrapped, so those the interpreter would take
+//! below `INT64_MIN` stop the program with an error instead. C leaves the
+//! order of evaluating operands unspecified, so operands reading input or
+//! calling functions are evaluated into temporaries first whenever there is
+//! more than one of them. Input is read with `scanf` and output written with
+//! `printf`, matching the raw output of the interpreter.
+
+use crate::transpile::{self, Resolver, Term};
+use crate::{Error, Expr, Function, Node, Program, Statement};
+
+const PRELUDE: &str = r#"#include <inttypes.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef int64_t num_t;
+
+#define LIMIT ((uint64_t)1 << 48)
+#define MAGNITUDE_MIN ((uint64_t)INT64_MAX + 1)
+
+static inline void fail_begin(int line) {
+    fflush(stdout);
+    fprintf(stderr, "line %d: error: ", line);
+}
+
+static inline void fail_end(void) {
+    fputc('\n', stderr);
+    exit(1);
+}
+
+static inline void fail(int line, const char *message) {
+    fail_begin(line);
+    fputs(message, stderr);
+    fail_end();
+}
+
+static inline num_t wrap(uint64_t v) {
+    return (num_t)(v & (LIMIT - 1));
+}
+
+static inline uint64_t magnitude(num_t v) {
+    return v < 0 ? 0 - (uint64_t)v : (uint64_t)v;
+}
+
+/* negative results are never wrapped, so those below INT64_MIN are errors */
+static inline num_t negative(int line, uint64_t v) {
+    if (v > MAGNITUDE_MIN) {
+        fail(line, "value does not fit in 64 bits");
+    }
+    return v == MAGNITUDE_MIN ? INT64_MIN : -(num_t)v;
+}
+
+static inline num_t add(int line, num_t a, num_t b) {
+    if (a < 0 && b < 0) {
+        return negative(line, magnitude(a) + magnitude(b));
+    }
+    return a + b > 0 ? wrap((uint64_t)(a + b)) : a + b;
+}
+
+static inline num_t sub(int line, num_t a, num_t b) {
+    if (a >= 0 && b < 0) {
+        return wrap((uint64_t)a + magnitude(b));
+    }
+    if (a < 0 && b > 0) {
+        return negative(line, magnitude(a) + (uint64_t)b);
+    }
+    return a - b > 0 ? wrap((uint64_t)(a - b)) : a - b;
+}
+
+static inline num_t mul(int line, num_t a, num_t b) {
+    uint64_t x = magnitude(a), y = magnitude(b);
+    if (a == 0 || b == 0) {
+        return 0;
+    }
+    if ((a < 0) == (b < 0)) {
+        return wrap(x * y);
+    }
+    if (x > MAGNITUDE_MIN / y) {
+        fail(line, "value does not fit in 64 bits");
+    }
+    return negative(line, x * y);
+}
+
+static inline num_t rem(num_t a, num_t b) {
+    uint64_t d = magnitude(b);
+    if (d == 0) {
+        return 0;
+    }
+    return wrap(a > 0 ? (uint64_t)a % d : (d - magnitude(a) % d) % d);
+}
+
+static inline num_t quot(int line, num_t a, num_t b) {
+    uint64_t d = magnitude(b);
+    num_t v;
+    if (d == 0) {
+        return 0;
+    }
+    /* the difference is a multiple of the divisor, rounding towards -inf */
+    v = sub(line, a, rem(a, b));
+    return v < 0 ? negative(line, magnitude(v) / d) : wrap((uint64_t)v / d);
+}
+
+static inline void put(FILE *stream, num_t v) {
+    fprintf(stream, "%" PRId64, v);
+}
+
+static inline void show(FILE *stream, int count, ...) {
+    va_list args;
+    int i;
+    va_start(args, count);
+    for (i = 0; i < count; i++) {
+        if (i > 0) {
+            fputc(' ', stream);
+        }
+        put(stream, va_arg(args, num_t));
+    }
+    va_end(args);
+    fputc('\n', stream);
+}
+
+static inline num_t eof(void) {
+    char c;
+    if (scanf(" %c", &c) != 1) {
+        return 1;
+    }
+    ungetc(c, stdin);
+    return 0;
+}
+
+static inline num_t scan(int line) {
+    /* 2^127 - 1, the largest input Nhotyp accepts before wrapping it */
+    static const char *largest = "170141183460469231731687303715884105727";
+    char chunk[64], *token = NULL, *digits;
+    size_t len = 0, n, i;
+    int minus, bad;
+    uint64_t v = 0;
+    if (scanf(" %63s", chunk) != 1) {
+        fail(line, "unexpected end of input");
+    }
+    do {
+        n = strlen(chunk);
+        token = realloc(token, len + n + 1);
+        if (token == NULL) {
+            fail(line, "out of memory");
+        }
+        memcpy(token + len, chunk, n + 1);
+        len += n;
+    } while (n == sizeof chunk - 1 && scanf("%63[^ \t\n\v\f\r]", chunk) == 1);
+    minus = token[0] == '-';
+    digits = token + (minus || token[0] == '+');
+    bad = *digits == '\0' || strspn(digits, "0123456789") != strlen(digits);
+    while (digits[0] == '0' && digits[1] != '\0') {
+        digits++;
+    }
+    n = strlen(digits);
+    if (n > strlen(largest) || (n == strlen(largest) && strcmp(digits, largest) > 0)) {
+        /* the magnitude of -2^127 is one more than the largest value */
+        bad = bad || !minus || strcmp(digits, "170141183460469231731687303715884105728") != 0;
+    }
+    if (bad) {
+        fail_begin(line);
+        fprintf(stderr, "invalid input \"%s\"", token);
+        fail_end();
+    }
+    for (i = 0; i < n; i++) {
+        uint64_t digit = (uint64_t)(digits[i] - '0');
+        if (!minus) {
+            v = (v * 10 + digit) & (LIMIT - 1);
+        } else if (v > (MAGNITUDE_MIN - digit) / 10) {
+            fail(line, "value does not fit in 64 bits");
+        } else {
+            v = v * 10 + digit;
+        }
+    }
+    free(token);
+    return minus ? negative(line, v) : wrap(v);
+}
+"#;
+
+/// C name of a variable or function, set apart from C keywords, macros and
+/// everything declared by the prelude. Nhotyp never allows a variable to
+/// share its name with a function.
+fn c_name(name: &str) -> String {
+    format!("nh_{}", name)
+}
+
+/// Quotes text as a C string literal.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+struct Emitter<'a> {
+    resolver: Resolver<'a>,
+    res: String,
+    depth: usize,
+    temps: usize,
+}
+
+impl Emitter<'_> {
+    fn line(&mut self, code: &str) {
+        for _ in 0..self.depth {
+            self.res.push_str("    ");
+        }
+        self.res.push_str(code);
+        self.res.push('\n');
+    }
+
+    fn temp(&mut self, value: String) -> String {
+        self.temps += 1;
+        let name = format!("t{}", self.temps);
+        self.line(&format!("num_t {} = {};", name, value));
+        name
+    }
+
+    /// Translates operands in order, hoisting them into temporaries unless
+    /// at most one of them is impure.
+    fn operands(&mut self, args: &[Term], line: usize) -> Vec<String> {
+        let hoist = args.iter().filter(|v| v.is_impure()).count() > 1;
+        let mut res = vec![];
+        for arg in args {
+            let value = self.term(arg, line);
+            res.push(match hoist && arg.is_impure() {
+                true => self.temp(value),
+                false => value,
+            });
+        }
+        res
+    }
+
+    fn term(&mut self, term: &Term, line: usize) -> String {
+        let (op, args) = match term {
+            // the magnitude of the smallest value is no valid literal
+            Term::Const(i64::MIN) => return format!("({} - 1)", i64::MIN + 1),
+            Term::Const(v) => return v.to_string(),
+            Term::Var(v) => return c_name(v),
+            Term::Builtin(op, args) => (*op, args),
+            Term::Call(name, args) => {
+                let args = self.operands(args, line);
+                return format!("{}({})", c_name(name), args.join(", "));
+            }
+        };
+        let v = self.operands(args, line);
+        match op {
+            "scan" => format!("scan({})", line + 1),
+            "eof" => String::from("eof()"),
+            "+" => format!("add({}, {}, {})", line + 1, v[0], v[1]),
+            "-" => format!("sub({}, {}, {})", line + 1, v[0], v[1]),
+            "*" => format!("mul({}, {}, {})", line + 1, v[0], v[1]),
+            "%" => format!("rem({}, {})", v[0], v[1]),
+            "/" => format!("quot({}, {}, {})", line + 1, v[0], v[1]),
+            // logical operators evaluate both operands, as in Nhotyp
+            "and" => format!("(num_t)(({} != 0) & ({} != 0))", v[0], v[1]),
+            "or" => format!("(num_t)(({} != 0) | ({} != 0))", v[0], v[1]),
+            "xor" => format!("(num_t)(({} != 0) ^ ({} != 0))", v[0], v[1]),
+            "not" => format!("(num_t)({} == 0)", v[0]),
+            _ => format!("(num_t)({} {} {})", v[0], op, v[1]),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, line: usize) -> Result<String, Error> {
+        let term = self.resolver.expr(expr, line)?;
+        Ok(self.term(&term, line))
+    }
+
+    fn print(&mut self, expr: &Expr, line: usize, stream: &str) -> Result<(), Error> {
+        let terms = self.resolver.print_args(expr, line)?;
+        // all values are evaluated before anything is written
+        let args = self.operands(&terms, line);
+        let args: String = args.iter().map(|v| format!(", (num_t)({})", v)).collect();
+        self.line(&format!("show({}, {}{});", stream, terms.len(), args));
+        Ok(())
+    }
+
+    fn assert(&mut self, expr: &Expr, line: usize) -> Result<(), Error> {
+        let cond = self.expr(expr, line)?;
+        // report each referenced variable once, in order of appearance
+        let mut vars: Vec<&str> = vec![];
+        for token in &expr.tokens {
+            if self.resolver.is_var(&token.value) && !vars.contains(&token.value.as_str()) {
+                vars.push(&token.value);
+            }
+        }
+        let source: Vec<_> = expr.tokens.iter().map(|t| t.value.as_str()).collect();
+        let mut message = format!("assertion \"{}\" failed", source.join(" "));
+        self.line(&format!("if (!{}) {{", cond));
+        self.line(&format!("    fail_begin({});", line + 1));
+        for (i, var) in vars.iter().enumerate() {
+            message += if i == 0 { ", with " } else { ", " };
+            message += &format!("{} = ", var);
+            self.line(&format!("    fputs({}, stderr);", quote(&message)));
+            self.line(&format!("    put(stderr, {});", c_name(var)));
+            message.clear();
+        }
+        if !message.is_empty() {
+            self.line(&format!("    fputs({}, stderr);", quote(&message)));
+        }
+        self.line("    fail_end();");
+        self.line("}");
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), Error> {
+        for stmt in &node.stmts {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> Result<(), Error> {
+        match stmt {
+            Statement::Assign { var, expr, line } => {
+                let value = self.expr(expr, *line)?;
+                self.line(&format!("{} = {};", c_name(&var.value), value));
+            }
+            Statement::Cond { expr, child, line } => {
+                let cond = self.expr(expr, *line)?;
+                self.line(&format!("if ({}) {{", cond));
+                self.depth += 1;
+                self.node(child)?;
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Loop { expr, child, line } => {
+                // temporaries of the condition are evaluated on every iteration
+                let term = self.resolver.expr(expr, *line)?;
+                let outer = std::mem::take(&mut self.res);
+                self.depth += 1;
+                let cond = self.term(&term, *line);
+                self.depth -= 1;
+                let setup = std::mem::replace(&mut self.res, outer);
+                match setup.is_empty() {
+                    true => self.line(&format!("while ({}) {{", cond)),
+                    false => {
+                        self.line("while (1) {");
+                        self.res += &setup;
+                        self.line(&format!("    if (!{}) {{", cond));
+                        self.line("        break;");
+                        self.line("    }");
+                    }
+                }
+                self.depth += 1;
+                self.node(child)?;
+                self.depth -= 1;
+                self.line("}");
+            }
+            Statement::Print { expr, line } => self.print(expr, *line, "stdout")?,
+            Statement::Eprint { expr, line } => self.print(expr, *line, "stderr")?,
+            Statement::Assert { expr, line } => self.assert(expr, *line)?,
+            Statement::Ret { expr, line } => {
+                let value = self.expr(expr, *line)?;
+                self.line(&format!("return {};", value));
+            }
+            // rejected by `check_function` already
+            Statement::Func { .. } | Statement::Test { .. } => (),
+        }
+        Ok(())
+    }
+}
+
+fn signature(func_name: &str, func: &Function) -> String {
+    let params: Vec<_> = func
+        .params
+        .iter()
+        .map(|v| format!("num_t {}", c_name(&v.value)))
+        .collect();
+    let params = match params.is_empty() {
+        true => String::from("void"),
+        false => params.join(", "),
+    };
+    format!("static num_t {}({})", c_name(func_name), params)
+}
+
+/// Translates a loaded program into a complete C file. Tests are left out.
+pub fn emit_c(prog: &Program) -> Result<String, Error> {
+    transpile::check_main(prog)?;
+    let funcs = transpile::functions(prog);
+    let mut res = String::from(PRELUDE);
+    res.push('\n');
+    for (name, func) in &funcs {
+        res += &format!("{};\n", signature(name, func));
+    }
+    for (name, func) in &funcs {
+        transpile::check_function(prog, func)?;
+        let mut emitter = Emitter {
+            resolver: Resolver::new(prog, func),
+            res: String::new(),
+            depth: 1,
+            temps: 0,
+        };
+        // `check_function` makes sure no variable is read before it is
+        // assigned, so any initial value will do
+        for var in transpile::locals(func) {
+            emitter.line(&format!("num_t {} = 0;", c_name(var)));
+        }
+        emitter.node(&func.root)?;
+        res += &format!("\n{} {{\n{}}}\n", signature(name, func), emitter.res);
+    }
+    res += &format!(
+        "\nint main(void) {{\n    return (int){}();\n}}\n",
+        c_name("main")
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::{BufferIo, Dialect};
+
+    /// Wrapping, division and remainders at their edges.
+    const ARITHMETIC: &str = "function main as
+    let big = * 140737488355327 140737488355327
+    let a = / -7 2
+    let b = % -7 2
+    let c = / 7 0
+    let d = % 7 0
+    let e = - -140737488355328 1
+    let f = scan
+    let g = * f f
+    print big a b c d e g
+    return 0
+end function
+";
+
+    fn load(source: &str) -> Program {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.set_dialect(Dialect::Extended);
+        prog.load(source).unwrap();
+        prog
+    }
+
+    fn interpret(prog: &Program, input: &str) -> String {
+        let mut io = BufferIo::new(input);
+        prog.execute_hooked(&mut io, &mut ()).unwrap();
+        io.stdout
+    }
+
+    /// Compiles and runs the C translation and returns what it writes to both
+    /// streams. Without a compiler around, says that the test is skipped.
+    fn compile(prog: &Program, input: &str, dir: &Path) -> Option<(String, String)> {
+        let source = dir.join("prog.c");
+        let binary = dir.join("prog");
+        fs::write(&source, emit_c(prog).unwrap()).unwrap();
+        let status = Command::new("cc")
+            .args(["-std=c99", "-O1", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status();
+        let status = match status {
+            Ok(v) => v,
+            Err(_) => {
+                // written past the test harness, which captures `eprintln!`
+                let _ = writeln!(std::io::stderr(), "skipping: cc not found");
+                return None;
+            }
+        };
+        assert!(status.success(), "generated C fails to compile");
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        Some((
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn prints_what_the_interpreter_prints() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let read = |name: &str| fs::read_to_string(root.join("samples").join(name)).unwrap();
+        let programs = [
+            (read("fastpow.nh"), "3 13"),
+            (read("fibonacci.nh"), "3\n1\n10\n20\n"),
+            (read("max_four.nh"), "3 9 -4 7"),
+            (String::from(ARITHMETIC), "-140737488355328"),
+        ];
+        let dir = std::env::temp_dir().join(format!("nhotyp-emit-c-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (source, input) in &programs {
+            let prog = load(source);
+            let expected = interpret(&prog, input);
+            match compile(&prog, input, &dir) {
+                Some((actual, _)) => assert_eq!(actual, expected),
+                None => break,
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stops_below_the_range_of_int64() {
+        let prog = load(
+            "function main as\n    let a = scan\n    print * a 3\n    return 0\nend function\n",
+        );
+        let dir = std::env::temp_dir().join(format!("nhotyp-emit-c-range-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        if let Some((stdout, _)) = compile(&prog, "-3074457345618258602", &dir) {
+            assert_eq!(stdout, "-9223372036854775806\n");
+        }
+        if let Some((stdout, stderr)) = compile(&prog, "-3074457345618258603", &dir) {
+            assert_eq!(stdout, "");
+            assert_eq!(stderr, "line 3: error: value does not fit in 64 bits\n");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! Translation of programs into Python.
+//!
+//! Prefix expressions become infix ones. Python integers are unbounded, so
+//! helper functions reproduce the interpreter's arithmetic exactly: positive
+//! results wrap around at 2^48, `%` never yields negative values and dividing
+//! by 0 yields 0. Operands are evaluated from left to right in both languages,
+//! and logical operators use `&`, `|` and `^` to evaluate both of them just
+//! like Nhotyp does. Output is the raw output of the interpreter.
+
+use crate::transpile::{self, Resolver, Term};
+use crate::{Error, Expr, Node, Program, Statement};
+
+const PRELUDE: &str = r#"import re
+import sys
+
+LIMIT = 1 << 48
+
+# whitespace-separated input tokens not yet consumed by `scan`
+pending = []
+
+
+def wrap(v):
+    return v & (LIMIT - 1) if v > 0 else v
+
+
+def rem(a, b):
+    b = abs(b)
+    if b == 0:
+        return 0
+    return wrap(a % b)
+
+
+def div(a, b):
+    d = abs(b)
+    if d == 0:
+        return 0
+    return wrap(wrap(a - rem(a, b)) // d)
+
+
+def fail(line, message):
+    sys.stdout.flush()
+    print(f"line {line}: error: {message}", file=sys.stderr)
+    sys.exit(1)
+
+
+def fill():
+    while not pending:
+        line = sys.stdin.readline()
+        if not line:
+            return False
+        pending.extend(line.split())
+    return True
+
+
+def eof():
+    return 0 if fill() else 1
+
+
+def scan(line):
+    if not fill():
+        fail(line, "unexpected end of input")
+    token = pending.pop(0)
+    # the token must fit into 128 bits before it wraps around
+    if not re.fullmatch(r"[+-]?[0-9]+", token) or not -(1 << 127) <= int(token) < 1 << 127:
+        fail(line, f'invalid input "{token}"')
+    return wrap(int(token))
+"#;
+
+/// Names of Python and of the prelude nothing may take.
+const RESERVED: &[&str] = &[
+    "as", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except",
+    "finally", "for", "from", "global", "import", "in", "is", "lambda", "nonlocal", "pass",
+    "raise", "try", "with", "yield", "abs", "int", "re", "sys", "pending", "wrap", "rem", "div",
+    "fail", "fill",
+];
+
+/// Python name of a variable or function. Nhotyp names never contain digits,
+/// so renamed ones cannot clash with others.
+fn name(name: &str) -> String {
+    match RESERVED.contains(&name) {
+        true => format!("{}_0", name),
+        false => String::from(name),
+    }
+}
+
+struct Emitter<'a> {
+    resolver: Resolver<'a>,
+    res: String,
+    depth: usize,
+}
+
+impl Emitter<'_> {
+    fn line(&mut self, code: &str) {
+        for _ in 0..self.depth {
+            self.res.push_str("    ");
+        }
+        self.res.push_str(code);
+        self.res.push('\n');
+    }
+
+    fn term(&self, term: &Term, line: usize) -> String {
+        let (op, args) = match term {
+            Term::Const(v) => return v.to_string(),
+            Term::Var(v) => return name(v),
+            Term::Builtin(op, args) => (*op, args),
+            Term::Call(func, args) => {
+                let args: Vec<_> = args.iter().map(|v| self.term(v, line)).collect();
+                return format!("{}({})", name(func), args.join(", "));
+            }
+        };
+        let v: Vec<_> = args.iter().map(|v| self.term(v, line)).collect();
+        match op {
+            "scan" => format!("scan({})", line + 1),
+            "eof" => String::from("eof()"),
+            "+" | "-" | "*" => format!("wrap({} {} {})", v[0], op, v[1]),
+            "%" => format!("rem({}, {})", v[0], v[1]),
+            "/" => format!("div({}, {})", v[0], v[1]),
+            _ => format!("int({})", self.condition(term, line)),
+        }
+    }
+
+    /// Translates a term used as a condition, where comparisons and logical
+    /// operators may stay booleans.
+    fn condition(&self, term: &Term, line: usize) -> String {
+        let (op, args) = match term {
+            Term::Builtin(op, args) => (*op, args),
+            _ => return self.term(term, line),
+        };
+        let v: Vec<_> = args.iter().map(|v| self.term(v, line)).collect();
+        match op {
+            "==" | "<" | ">" | "<=" | ">=" | "!=" => format!("{} {} {}", v[0], op, v[1]),
+            "and" => format!("({} != 0) & ({} != 0)", v[0], v[1]),
+            "or" => format!("({} != 0) | ({} != 0)", v[0], v[1]),
+            "xor" => format!("({} != 0) ^ ({} != 0)", v[0], v[1]),
+            "not" => format!("{} == 0", v[0]),
+            _ => self.term(term, line),
+        }
+    }
+
+    fn print(&mut self, expr: &Expr, line: usize, stream: &str) -> Result<(), Error> {
+        let mut args: Vec<_> = self
+            .resolver
+            .print_args(expr, line)?
+            .iter()
+            .map(|v| self.term(v, line))
+            .collect();
+        if !stream.is_empty() {
+            args.push(format!("file={}", stream));
+        }
+        self.line(&format!("print({})", args.join(", ")));
+        Ok(())
+    }
+
+    fn assert(&mut self, expr: &Expr, line: usize) -> Result<(), Error> {
+        let cond = self.condition(&self.resolver.expr(expr, line)?, line);
+        // report each referenced variable once, in order of appearance
+        let mut vars: Vec<&str> = vec![];
+        for token in &expr.tokens {
+            if self.resolver.is_var(&token.value) && !vars.contains(&token.value.as_str()) {
+                vars.push(&token.value);
+            }
+        }
+        let source: Vec<_> = expr.tokens.iter().map(|t| t.value.as_str()).collect();
+        let mut message = format!("assertion \"{}\" failed", source.join(" "));
+        if !vars.is_empty() {
+            let shown: Vec<_> = vars
+                .iter()
+                .map(|v| format!("{} = {{{}}}", v, name(v)))
+                .collect();
+            message = format!("{}, with {}", message, shown.join(", "));
+        }
+        self.line(&format!("if not ({}):", cond));
+        self.line(&format!("    fail({}, f'{}')", line + 1, message));
+        Ok(())
+    }
+
+    fn block(&mut self, node: &Node) -> Result<(), Error> {
+        self.depth += 1;
+        if node.stmts.is_empty() {
+            self.line("pass");
+        }
+        for stmt in &node.stmts {
+            self.statement(stmt)?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Statement) -> Result<(), Error> {
+        match stmt {
+            Statement::Assign { var, expr, line } => {
+                let value = self.term(&self.resolver.expr(expr, *line)?, *line);
+                self.line(&format!("{} = {}", name(&var.value), value));
+            }
+            Statement::Cond { expr, child, line } => {
+                let cond = self.condition(&self.resolver.expr(expr, *line)?, *line);
+                self.line(&format!("if {}:", cond));
+                self.block(child)?;
+            }
+            Statement::Loop { expr, child, line } => {
+                let cond = self.condition(&self.resolver.expr(expr, *line)?, *line);
+                self.line(&format!("while {}:", cond));
+                self.block(child)?;
+            }
+            Statement::Print { expr, line } => self.print(expr, *line, "")?,
+            Statement::Eprint { expr, line } => self.print(expr, *line, "sys.stderr")?,
+            Statement::Assert { expr, line } => self.assert(expr, *line)?,
+            Statement::Ret { expr, line } => {
+                let value = self.term(&self.resolver.expr(expr, *line)?, *line);
+                self.line(&format!("return {}", value));
+            }
+            // rejected by `check_function` already
+            Statement::Func { .. } | Statement::Test { .. } => (),
+        }
+        Ok(())
+    }
+}
+
+/// Translates a loaded program into a complete Python script. Tests are left
+/// out.
+pub fn emit_python(prog: &Program) -> Result<String, Error> {
+    transpile::check_main(prog)?;
+    let mut res = String::from(PRELUDE);
+    for (func_name, func) in transpile::functions(prog) {
+        transpile::check_function(prog, func)?;
+        let mut emitter = Emitter {
+            resolver: Resolver::new(prog, func),
+            res: String::new(),
+            depth: 0,
+        };
+        emitter.block(&func.root)?;
+        let params: Vec<_> = func.params.iter().map(|v| name(&v.value)).collect();
+        res += &format!(
+            "\n\ndef {}({}):\n{}",
+            name(func_name),
+            params.join(", "),
+            emitter.res
+        );
+    }
+    // recursion runs about as deep as in the interpreter
+    res += "\n\nif __name__ == \"__main__\":\n";
+    res += "    sys.setrecursionlimit(10000)\n";
+    res += &format!("    sys.exit({}() & 0xFF)\n", name("main"));
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::{BufferIo, Dialect};
+
+    /// Wrapping, division and remainders at their edges.
+    const ARITHMETIC: &str = "function main as
+    let big = * 140737488355327 140737488355327
+    let a = / -7 2
+    let b = % -7 2
+    let c = / 7 0
+    let d = % 7 0
+    let e = - -140737488355328 1
+    let f = scan
+    let g = * f f
+    print big a b c d e g
+    return 0
+end function
+";
+
+    fn load(source: &str) -> Program {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.set_dialect(Dialect::Extended);
+        prog.load(source).unwrap();
+        prog
+    }
+
+    fn interpret(prog: &Program, input: &str) -> String {
+        let mut io = BufferIo::new(input);
+        prog.execute_hooked(&mut io, &mut ()).unwrap();
+        io.stdout
+    }
+
+    /// Runs the Python translation. Without an interpreter around, says that
+    /// the test is skipped.
+    fn run(prog: &Program, input: &str, dir: &Path) -> Option<String> {
+        let script = dir.join("prog.py");
+        fs::write(&script, emit_python(prog).unwrap()).unwrap();
+        let child = Command::new("python3")
+            .arg(&script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(v) => v,
+            Err(_) => {
+                // written past the test harness, which captures `eprintln!`
+                let _ = writeln!(std::io::stderr(), "skipping: python3 not found");
+                return None;
+            }
+        };
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn prints_what_the_interpreter_prints() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let read = |name: &str| fs::read_to_string(root.join("samples").join(name)).unwrap();
+        let programs = [
+            (read("fastpow.nh"), "3 13"),
+            (read("fibonacci.nh"), "3\n1\n10\n20\n"),
+            (read("max_four.nh"), "3 9 -4 7"),
+            (String::from(ARITHMETIC), "-140737488355328"),
+        ];
+        let dir = std::env::temp_dir().join(format!("nhotyp-emit-python-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (source, input) in &programs {
+            let prog = load(source);
+            let expected = interpret(&prog, input);
+            match run(&prog, input, &dir) {
+                Some(actual) => assert_eq!(actual, expected),
+                None => break,
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Source formatting with canonical indentation and spacing.
+//!
+//! The program is parsed losslessly and re-emitted from its tree: one
+//! statement per line with tokens joined by single spaces, blocks indented by
+//! 4 spaces per level, and comments kept next to the statements they belong
+//! to. Runs of blank lines collapse into one.
+
+use crate::{parse_lossless, Dialect, Error, Expr, Node, Statement, Token};
+
+const INDENT: &str = "    ";
+
+struct Printer {
+    res: String,
+    depth: usize,
+    blank: bool,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str, remark: &Option<String>) {
+        if self.blank {
+            self.res.push('\n');
+            self.blank = false;
+        }
+        for _ in 0..self.depth {
+            self.res.push_str(INDENT);
+        }
+        self.res.push_str(text);
+        if let Some(remark) = remark {
+            self.res.push(' ');
+            self.res.push_str(remark.trim_end());
+        }
+        self.res.push('\n');
+    }
+
+    /// Prints full-line comments, dropping blank lines at the very start.
+    fn comments(&mut self, comments: &[String]) {
+        for comment in comments {
+            match comment.is_empty() {
+                true => self.blank = !self.res.is_empty(),
+                false => self.line(comment.trim_end(), &None),
+            }
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        for (i, stmt) in node.stmts.iter().enumerate() {
+            self.comments(&node.comments[i]);
+            self.statement(stmt, &node.remarks[i]);
+        }
+        self.comments(&node.trailing);
+    }
+
+    fn block(&mut self, head: String, remark: &Option<String>, child: &Node, term: &str) {
+        self.line(&head, remark);
+        self.depth += 1;
+        self.node(child);
+        self.depth -= 1;
+        self.line(&format!("end {}", term), &child.end_remark);
+    }
+
+    fn statement(&mut self, stmt: &Statement, remark: &Option<String>) {
+        match stmt {
+            Statement::Assign { var, expr, .. } => {
+                self.line(&format!("let {} = {}", var.value, words(expr)), remark)
+            }
+            Statement::Cond { expr, child, .. } => {
+                self.block(format!("if {} then", words(expr)), remark, child, "if")
+            }
+            Statement::Loop { expr, child, .. } => {
+                self.block(format!("while {} do", words(expr)), remark, child, "while")
+            }
+            Statement::Print { expr, .. } => self.simple("print", expr, remark),
+            Statement::Eprint { expr, .. } => self.simple("eprint", expr, remark),
+            Statement::Ret { expr, .. } => self.simple("return", expr, remark),
+            Statement::Assert { expr, .. } => self.simple("assert", expr, remark),
+            Statement::Func {
+                name,
+                params,
+                child,
+                ..
+            } => {
+                let mut head = vec![String::from("function"), name.value.clone()];
+                head.extend(params.iter().map(|v: &Token| v.value.clone()));
+                head.push(String::from("as"));
+                self.block(head.join(" "), remark, child, "function")
+            }
+            Statement::Test { name, child, .. } => {
+                self.block(format!("test {} as", name.value), remark, child, "test")
+            }
+        }
+    }
+
+    fn simple(&mut self, keyword: &str, expr: &Expr, remark: &Option<String>) {
+        let mut text = String::from(keyword);
+        if !expr.tokens.is_empty() {
+            text.push(' ');
+            text.push_str(&words(expr));
+        }
+        self.line(&text, remark);
+    }
+}
+
+fn words(expr: &Expr) -> String {
+    let words: Vec<_> = expr.tokens.iter().map(|v| v.value.as_str()).collect();
+    words.join(" ")
+}
+
+/// Formats a whole source file. Programs that fail to parse in the given
+/// dialect are rejected, as their block structure cannot be trusted.
+pub fn format_source(source: &str, dialect: Dialect) -> Result<String, Error> {
+    let root = parse_lossless(source, dialect)?;
+    let mut printer = Printer {
+        res: String::new(),
+        depth: 0,
+        blank: false,
+    };
+    printer.node(&root);
+    Ok(printer.res)
+}
+
+/// Checks whether source code is already formatted.
+pub fn is_formatted(source: &str, dialect: Dialect) -> Result<bool, Error> {
+    Ok(format_source(source, dialect)? == source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "# sums\n\n\n  function  main as   # entry\n\t# inner\nlet   a = +  1 2\n\n\n\nif a then\nprint a\n  # before end\n    end if # closing\n  return a\nend function\n\n# trailing\n\n";
+
+    const TIDY: &str = "# sums\n\nfunction main as # entry\n    # inner\n    let a = + 1 2\n\n    if a then\n        print a\n        # before end\n    end if # closing\n    return a\nend function\n\n# trailing\n";
+
+    #[test]
+    fn formats_samples_idempotently() {
+        let samples = [
+            include_str!("../samples/fastpow.nh"),
+            include_str!("../samples/fibonacci.nh"),
+            include_str!("../samples/max_four.nh"),
+        ];
+        for sample in &samples {
+            let once = format_source(sample, Dialect::Standard).unwrap();
+            assert_eq!(format_source(&once, Dialect::Standard).unwrap(), once);
+            assert_eq!(is_formatted(&once, Dialect::Standard), Ok(true));
+        }
+    }
+
+    #[test]
+    fn keeps_comments_blank_lines_and_remarks() {
+        assert_eq!(format_source(MESSY, Dialect::Standard).unwrap(), TIDY);
+        assert_eq!(is_formatted(MESSY, Dialect::Standard), Ok(false));
+        assert_eq!(is_formatted(TIDY, Dialect::Standard), Ok(true));
+    }
+
+    #[test]
+    fn rejects_programs_of_other_dialects() {
+        let source = "function main as\n    print + 1 2\n    return 0\nend function\n";
+        assert_eq!(is_formatted(source, Dialect::Extended), Ok(true));
+        assert!(format_source(source, Dialect::Standard).is_err());
+        assert_eq!(
+            format_source("function main as\n", Dialect::Standard),
+            Err(Error::UnclosedBlock)
+        );
+    }
+}
@@ -0,0 +1,555 @@
+//! Conversion of a C-like infix syntax into Nhotyp.
+//!
+//! Functions are written as `function name(a, b) { ... }` and contain
+//! statements ending in semicolons: `x = expr;`, `return expr;`,
+//! `print(a, b);`, `eprint(a, b);`, `assert(expr);` as well as
+//! `if (expr) { ... }` and `while (expr) { ... }`. Expressions use the infix
+//! symbols and precedence from the interpreter's table of built-in operators,
+//! unary `-`, and call functions, `scan()` and `eof()` with parentheses.
+//! Comments start with `//`, and those on lines of their own are carried
+//! over. In the standard dialect, printed values other than variables are
+//! stored in temporaries first.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::lexer::{self, Kind};
+use crate::{builtin_arity, formatter, Builtin, Dialect, Error, BUILTINS};
+
+/// Symbols other than those of operators.
+const PUNCTUATION: &[&str] = &["=", "(", ")", "{", "}", ",", ";"];
+
+struct Tok {
+    text: String,
+    line: usize,
+}
+
+fn symbols() -> impl Iterator<Item = &'static str> {
+    let ops = BUILTINS
+        .iter()
+        .filter_map(|v| v.infix)
+        .map(|(symbol, _)| symbol);
+    ops.chain(PUNCTUATION.iter().copied())
+}
+
+fn tokenize(lines: &[String]) -> Result<Vec<Tok>, Error> {
+    let mut res = vec![];
+    for (line, source) in lines.iter().enumerate() {
+        let chars: Vec<char> = source.chars().collect();
+        let mut ptr = 0;
+        let first = res.len();
+        while ptr < chars.len() {
+            let c = chars[ptr];
+            let rest: String = chars[ptr..].iter().collect();
+            let start = ptr;
+            if c.is_whitespace() {
+                ptr += 1;
+                continue;
+            } else if rest.starts_with("//") {
+                // only comments on lines of their own are kept
+                if res.len() == first {
+                    res.push(Tok {
+                        text: String::from(rest.trim_end()),
+                        line,
+                    });
+                }
+                break;
+            } else if c.is_ascii_alphanumeric() || c == '_' {
+                while ptr < chars.len() && (chars[ptr].is_ascii_alphanumeric() || chars[ptr] == '_')
+                {
+                    ptr += 1;
+                }
+            } else if let Some(symbol) = symbols()
+                .filter(|v| rest.starts_with(*v))
+                .max_by_key(|v| v.len())
+            {
+                // `<=` is no `<` followed by `=`
+                ptr += symbol.len();
+            } else {
+                return Err(Error::IllegalChar { line, value: c });
+            }
+            res.push(Tok {
+                text: chars[start..ptr].iter().collect(),
+                line,
+            });
+        }
+    }
+    Ok(res)
+}
+
+/// Parameter counts of all functions, so that calls can be checked before
+/// the functions they call are converted.
+fn signatures(toks: &[Tok]) -> HashMap<&str, usize> {
+    let mut res = HashMap::new();
+    for (i, tok) in toks.iter().enumerate() {
+        if tok.text != "function" {
+            continue;
+        }
+        if let (Some(name), Some("(")) = (toks.get(i + 1), toks.get(i + 2).map(|v| &v.text[..])) {
+            let params = toks[i + 3..]
+                .iter()
+                .take_while(|v| v.text != ")")
+                .filter(|v| v.text != ",")
+                .count();
+            res.insert(name.text.as_str(), params);
+        }
+    }
+    res
+}
+
+struct Converter<'a> {
+    toks: &'a [Tok],
+    ptr: usize,
+    funcs: HashMap<&'a str, usize>,
+    // every word of the source, which temporaries must not be named like
+    words: HashSet<&'a str>,
+    dialect: Dialect,
+    res: Vec<String>,
+}
+
+impl Converter<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.toks.get(self.ptr).map(|v| v.text.as_str())
+    }
+
+    /// Line of the upcoming token, or of the last one at the end.
+    fn line(&self) -> usize {
+        match self
+            .toks
+            .get(self.ptr.min(self.toks.len().saturating_sub(1)))
+        {
+            Some(tok) => tok.line,
+            None => 0,
+        }
+    }
+
+    fn expect(&mut self, text: &str, err: Error) -> Result<(), Error> {
+        match self.peek() == Some(text) {
+            true => {
+                self.ptr += 1;
+                Ok(())
+            }
+            false => Err(err),
+        }
+    }
+
+    /// Takes a variable or function name.
+    fn name(&mut self, err: Error) -> Result<String, Error> {
+        let (text, line) = match self.toks.get(self.ptr) {
+            Some(tok) => (tok.text.as_str(), tok.line),
+            None => return Err(err),
+        };
+        match lexer::classify(text, line)? {
+            Kind::Ident => (),
+            Kind::Keyword => {
+                return Err(Error::DuplicateToken {
+                    line,
+                    value: String::from(text),
+                })
+            }
+            _ => return Err(err),
+        }
+        self.ptr += 1;
+        Ok(String::from(text))
+    }
+
+    fn program(&mut self) -> Result<(), Error> {
+        while let Some(text) = self.peek() {
+            let line = self.line();
+            match text {
+                _ if text.starts_with("//") => self.comment(),
+                "function" => {
+                    self.function()?;
+                    self.res.push(String::new());
+                }
+                "}" => return Err(Error::MalformedEnd { line }),
+                _ => return Err(Error::WildStatement { line }),
+            }
+        }
+        Ok(())
+    }
+
+    fn comment(&mut self) {
+        let text = &self.toks[self.ptr].text[2..];
+        self.res.push(format!("#{}", text));
+        self.ptr += 1;
+    }
+
+    fn function(&mut self) -> Result<(), Error> {
+        let line = self.line();
+        let err = || Error::MalformedFunc { line };
+        self.ptr += 1;
+        let mut header = vec![String::from("function"), self.name(err())?];
+        self.expect("(", err())?;
+        if self.peek() != Some(")") {
+            header.push(self.name(err())?);
+            while self.peek() == Some(",") {
+                self.ptr += 1;
+                header.push(self.name(err())?);
+            }
+        }
+        self.expect(")", err())?;
+        header.push(String::from("as"));
+        self.res.push(header.join(" "));
+        self.block("function", err())
+    }
+
+    /// Converts a block in braces, closing it with `end <term>`.
+    fn block(&mut self, term: &str, err: Error) -> Result<(), Error> {
+        self.expect("{", err)?;
+        loop {
+            match self.peek() {
+                Some("}") => break,
+                Some(_) => self.statement()?,
+                None => return Err(Error::UnclosedBlock),
+            }
+        }
+        self.ptr += 1;
+        self.res.push(format!("end {}", term));
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), Error> {
+        let line = self.line();
+        let head = String::from(self.peek().unwrap_or_default());
+        match head.as_str() {
+            _ if head.starts_with("//") => self.comment(),
+            "function" => return Err(Error::WildFunction { line }),
+            "if" | "while" => {
+                let err = || match head.as_str() {
+                    "if" => Error::MalformedCond { line },
+                    _ => Error::MalformedLoop { line },
+                };
+                let word = if head == "if" { "then" } else { "do" };
+                self.ptr += 1;
+                self.expect("(", err())?;
+                let cond = self.expr()?;
+                self.expect(")", err())?;
+                self.res
+                    .push(format!("{} {} {}", head, cond.join(" "), word));
+                self.block(&head, err())?;
+            }
+            "return" => {
+                self.ptr += 1;
+                let value = self.expr()?;
+                self.expect(";", Error::MalformedRet { line })?;
+                self.res.push(format!("return {}", value.join(" ")));
+            }
+            "print" | "eprint" => {
+                let err = || Error::BadExpression { line };
+                self.ptr += 1;
+                self.expect("(", err())?;
+                let mut args = vec![];
+                if self.peek() != Some(")") {
+                    args.push(self.expr()?);
+                    while self.peek() == Some(",") {
+                        self.ptr += 1;
+                        args.push(self.expr()?);
+                    }
+                }
+                self.expect(")", err())?;
+                self.expect(";", err())?;
+                let mut stmt = vec![head];
+                let mut temps = temps(&self.words);
+                for arg in args {
+                    if self.dialect == Dialect::Extended || is_var(&arg, line) {
+                        stmt.extend(arg);
+                        continue;
+                    }
+                    // the standard dialect prints nothing but variables
+                    let temp = temps.next().unwrap_or_default();
+                    self.res.push(format!("let {} = {}", temp, arg.join(" ")));
+                    stmt.push(temp);
+                }
+                self.res.push(stmt.join(" "));
+            }
+            "assert" => {
+                let err = || Error::MalformedAssert { line };
+                self.ptr += 1;
+                self.expect("(", err())?;
+                let cond = self.expr()?;
+                self.expect(")", err())?;
+                self.expect(";", err())?;
+                self.res.push(format!("assert {}", cond.join(" ")));
+            }
+            _ => {
+                if self.toks.get(self.ptr + 1).map(|v| v.text.as_str()) != Some("=") {
+                    return Err(Error::UnknownToken { line, value: head });
+                }
+                let err = || Error::MalformedAssign { line };
+                let var = self.name(err())?;
+                self.ptr += 1;
+                let value = self.expr()?;
+                self.expect(";", err())?;
+                self.res.push(format!("let {} = {}", var, value.join(" ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts an expression into prefix notation, one token per element.
+    fn expr(&mut self) -> Result<Vec<String>, Error> {
+        self.binary(1)
+    }
+
+    /// Converts operators binding at least as tight as `level`, with the
+    /// precedence taken from the interpreter's table of built-in operators.
+    fn binary(&mut self, level: u8) -> Result<Vec<String>, Error> {
+        let binary = |v: &&Builtin| v.arity == 2 && v.infix.is_some();
+        if !BUILTINS
+            .iter()
+            .filter(binary)
+            .any(|v| v.infix.map(|(_, v)| v) >= Some(level))
+        {
+            return self.unary();
+        }
+        let mut res = self.binary(level + 1)?;
+        loop {
+            let text = self.peek().unwrap_or_default();
+            let op = BUILTINS
+                .iter()
+                .filter(binary)
+                .find(|v| v.infix == Some((text, level)));
+            let op = match op {
+                Some(v) => v.name,
+                None => return Ok(res),
+            };
+            self.ptr += 1;
+            let rhs = self.binary(level + 1)?;
+            let mut expr = vec![String::from(op)];
+            expr.append(&mut res);
+            expr.extend(rhs);
+            res = expr;
+        }
+    }
+
+    fn unary(&mut self) -> Result<Vec<String>, Error> {
+        let text = self.peek().unwrap_or_default();
+        let op = BUILTINS
+            .iter()
+            .find(|v| v.arity == 1 && v.infix.map(|(symbol, _)| symbol) == Some(text));
+        let mut res = match (op, text) {
+            (Some(op), _) => vec![String::from(op.name)],
+            // negative literals need no operator
+            (None, "-") => match self.toks.get(self.ptr + 1) {
+                Some(tok) if tok.text.chars().all(|c| c.is_ascii_digit()) => {
+                    self.ptr += 1;
+                    let mut operand = self.primary()?;
+                    operand[0] = format!("-{}", operand[0]);
+                    return Ok(operand);
+                }
+                _ => vec![String::from("-"), String::from("0")],
+            },
+            _ => return self.primary(),
+        };
+        self.ptr += 1;
+        res.extend(self.unary()?);
+        Ok(res)
+    }
+
+    fn primary(&mut self) -> Result<Vec<String>, Error> {
+        let line = self.line();
+        let err = Error::BadExpression { line };
+        let text = match self.peek() {
+            Some(v) => String::from(v),
+            None => return Err(err),
+        };
+        if text == "(" {
+            self.ptr += 1;
+            let res = self.expr()?;
+            self.expect(")", err)?;
+            return Ok(res);
+        }
+        // a missing operand is no illegal word
+        if symbols().any(|v| v == text) {
+            return Err(err);
+        }
+        match lexer::classify(&text, line)? {
+            Kind::Int => return Ok(vec![self.take()]),
+            Kind::Ident | Kind::Keyword => (),
+            _ => return Err(err),
+        }
+        let is_call = self.toks.get(self.ptr + 1).map(|v| v.text.as_str()) == Some("(");
+        let arity = builtin_arity(&text).or_else(|| self.funcs.get(text.as_str()).copied());
+        if !is_call {
+            // `scan` and `eof` may also go without parentheses
+            return match arity {
+                Some(0) if builtin_arity(&text).is_some() => Ok(vec![self.take()]),
+                _ => self.name(err).map(|v| vec![v]),
+            };
+        }
+        let arity = match arity {
+            Some(v) => v,
+            None => return Err(Error::UndeclaredToken { line, value: text }),
+        };
+        self.ptr += 2;
+        let mut res = vec![text.clone()];
+        let mut args = 0;
+        if self.peek() != Some(")") {
+            res.extend(self.expr()?);
+            args += 1;
+            while self.peek() == Some(",") {
+                self.ptr += 1;
+                res.extend(self.expr()?);
+                args += 1;
+            }
+        }
+        self.expect(")", err)?;
+        if args != arity {
+            return Err(Error::BadArguments { line, value: text });
+        }
+        Ok(res)
+    }
+
+    fn take(&mut self) -> String {
+        self.ptr += 1;
+        self.toks[self.ptr - 1].text.clone()
+    }
+}
+
+/// Names for temporaries, clashing with no word of the source.
+fn temps<'a>(words: &'a HashSet<&str>) -> impl Iterator<Item = String> + 'a {
+    (0..).filter_map(move |mut i: usize| {
+        let mut name = String::from("tmp_");
+        loop {
+            name.push((b'a' + (i % 26) as u8) as char);
+            i /= 26;
+            if i == 0 {
+                break;
+            }
+        }
+        match words.contains(name.as_str()) {
+            true => None,
+            false => Some(name),
+        }
+    })
+}
+
+fn is_var(expr: &[String], line: usize) -> bool {
+    match expr {
+        [word] => lexer::classify(word, line).ok() == Some(Kind::Ident),
+        _ => false,
+    }
+}
+
+/// Converts a program in infix syntax into formatted Nhotyp source.
+pub fn convert(source: &str, dialect: Dialect) -> Result<String, Error> {
+    let lines = lexer::split_lines(source);
+    let toks = tokenize(&lines)?;
+    let mut converter = Converter {
+        toks: &toks,
+        ptr: 0,
+        funcs: signatures(&toks),
+        words: toks.iter().map(|v| v.text.as_str()).collect(),
+        dialect,
+        res: vec![],
+    };
+    converter.program()?;
+    formatter::format_source(&converter.res.join("\n"), dialect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BufferIo, Program};
+
+    /// Converts the body of a `main` function, returning its statements.
+    fn body(source: &str, dialect: Dialect) -> Result<Vec<String>, Error> {
+        let source = format!("function main() {{\n{}\n}}\n", source);
+        let res = convert(&source, dialect)?;
+        let lines: Vec<_> = res.lines().map(|v| String::from(v.trim())).collect();
+        Ok(lines[1..lines.len() - 1].to_vec())
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        let res = body("x = 1 + 2 * 3 - 4;\ny = (1 + 2) * 3;", Dialect::Standard).unwrap();
+        assert_eq!(res, ["let x = - + 1 * 2 3 4", "let y = * + 1 2 3"]);
+        let res = body("x = 1 < 2 && 3 == 4 || !x ^ 5;", Dialect::Standard).unwrap();
+        assert_eq!(res, ["let x = or and < 1 2 == 3 4 xor not x 5"]);
+    }
+
+    #[test]
+    fn converts_unary_minus() {
+        let res = body("x = -5;\ny = -x;\nz = 1 - -2;", Dialect::Standard).unwrap();
+        assert_eq!(res, ["let x = -5", "let y = - 0 x", "let z = - 1 -2"]);
+    }
+
+    #[test]
+    fn checks_calls_against_arities() {
+        let source = "function f(a, b) {\n    return a + b;\n}\nfunction main() {\n    x = f(scan(), eof);\n    return f(x, 1);\n}\n";
+        let res = convert(source, Dialect::Standard).unwrap();
+        assert!(res.contains("let x = f scan eof\n    return f x 1\n"));
+        assert_eq!(
+            body("x = f(1);", Dialect::Standard),
+            Err(Error::UndeclaredToken {
+                line: 1,
+                value: String::from("f")
+            })
+        );
+        assert_eq!(
+            body("x = scan(1);", Dialect::Standard),
+            Err(Error::BadArguments {
+                line: 1,
+                value: String::from("scan")
+            })
+        );
+    }
+
+    #[test]
+    fn hoists_printed_expressions_in_the_standard_dialect() {
+        let source = "tmp_a = 1;\nprint(tmp_a, tmp_a + 1, 2);";
+        let res = body(source, Dialect::Standard).unwrap();
+        assert_eq!(
+            res,
+            [
+                "let tmp_a = 1",
+                "let tmp_b = + tmp_a 1",
+                "let tmp_c = 2",
+                "print tmp_a tmp_b tmp_c",
+            ]
+        );
+        let res = body(source, Dialect::Extended).unwrap();
+        assert_eq!(res, ["let tmp_a = 1", "print tmp_a + tmp_a 1 2"]);
+    }
+
+    #[test]
+    fn keeps_comments_on_lines_of_their_own() {
+        let source =
+            "// sums\nfunction main() {\n    // twice\n    x = 1; // dropped\n    return x;\n}\n";
+        assert_eq!(
+            convert(source, Dialect::Standard).unwrap(),
+            "# sums\nfunction main as\n    # twice\n    let x = 1\n    return x\nend function\n"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_programs() {
+        assert_eq!(
+            body("if (1) {\n} else {\n}", Dialect::Standard),
+            Err(Error::UnknownToken {
+                line: 2,
+                value: String::from("else")
+            })
+        );
+        assert_eq!(
+            convert("function main() {\n", Dialect::Standard),
+            Err(Error::UnclosedBlock)
+        );
+        assert_eq!(
+            body("x = 1 +;", Dialect::Standard),
+            Err(Error::BadExpression { line: 1 })
+        );
+    }
+
+    #[test]
+    fn converted_programs_run() {
+        let source = "function main() {\n    n = scan();\n    i = 0;\n    while (i < n) {\n        if (i % 2 == 0) {\n            print(i * i);\n        }\n        i = i + 1;\n    }\n    return 0;\n}\n";
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.load(&convert(source, Dialect::Standard).unwrap())
+            .unwrap();
+        let mut io = BufferIo::new("5");
+        assert_eq!(prog.execute_hooked(&mut io, &mut ()), Ok(0));
+        assert_eq!(io.stdout, "0\n4\n16\n");
+    }
+}
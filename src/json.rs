@@ -0,0 +1,461 @@
+//! A minimal JSON representation for the editor protocols.
+//!
+//! Both the language server and the debug adapter exchange JSON messages
+//! framed by a `Content-Length` header. Objects keep their keys in insertion
+//! order and numbers are stored as `f64`, which covers every integer the
+//! interpreter can produce.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    /// Builds an object from key-value pairs.
+    pub fn object(pairs: Vec<(&str, Value)>) -> Self {
+        Self::Object(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (String::from(k), v))
+                .collect(),
+        )
+    }
+
+    /// Looks up a key of an object, yielding `Null` for anything missing.
+    pub fn get(&self, key: &str) -> &Value {
+        match self {
+            Self::Object(pairs) => pairs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&NULL, |(_, v)| v),
+            _ => &NULL,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Self::Null
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Number(v) if v.fract() == 0.0 => Some(*v as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Elements of an array, or nothing for any other value.
+    pub fn as_array(&self) -> &[Value] {
+        match self {
+            Self::Array(v) => v,
+            _ => &[],
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut ptr = 0;
+        let res = parse_value(&chars, &mut ptr, 0)?;
+        skip_whitespace(&chars, &mut ptr);
+        match ptr < chars.len() {
+            true => Err(format!("trailing characters at {}", ptr)),
+            false => Ok(res),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Self::Number(v as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Self {
+        Self::Number(v as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Self::String(String::from(v))
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Self::String(v)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Self::Array(v)
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::Number(v) if v.fract() == 0.0 && v.abs() < 1e15 => write!(f, "{}", *v as i64),
+            Self::Number(v) => write!(f, "{}", v),
+            Self::String(v) => write_str(f, v),
+            Self::Array(v) => {
+                f.write_str("[")?;
+                for (i, item) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(v) => {
+                f.write_str("{")?;
+                for (i, (key, item)) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", item)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn skip_whitespace(chars: &[char], ptr: &mut usize) {
+    while *ptr < chars.len() && chars[*ptr].is_ascii_whitespace() {
+        *ptr += 1;
+    }
+}
+
+fn expect_word(chars: &[char], ptr: &mut usize, word: &str, value: Value) -> Result<Value, String> {
+    for c in word.chars() {
+        if chars.get(*ptr) != Some(&c) {
+            return Err(format!("expected {:?} at {}", word, ptr));
+        }
+        *ptr += 1;
+    }
+    Ok(value)
+}
+
+/// Deepest nesting of arrays and objects accepted, keeping the recursion
+/// well within the stack of any thread.
+pub const MAX_DEPTH: usize = 256;
+
+fn parse_value(chars: &[char], ptr: &mut usize, depth: usize) -> Result<Value, String> {
+    skip_whitespace(chars, ptr);
+    let c = chars.get(*ptr);
+    if depth >= MAX_DEPTH && (c == Some(&'[') || c == Some(&'{')) {
+        return Err(format!("nesting deeper than {} at {}", MAX_DEPTH, ptr));
+    }
+    match c {
+        Some('n') => expect_word(chars, ptr, "null", Value::Null),
+        Some('t') => expect_word(chars, ptr, "true", Value::Bool(true)),
+        Some('f') => expect_word(chars, ptr, "false", Value::Bool(false)),
+        Some('"') => parse_string(chars, ptr).map(Value::String),
+        Some('[') => {
+            *ptr += 1;
+            let mut res = vec![];
+            skip_whitespace(chars, ptr);
+            if chars.get(*ptr) == Some(&']') {
+                *ptr += 1;
+                return Ok(Value::Array(res));
+            }
+            loop {
+                res.push(parse_value(chars, ptr, depth + 1)?);
+                skip_whitespace(chars, ptr);
+                match chars.get(*ptr) {
+                    Some(',') => *ptr += 1,
+                    Some(']') => break,
+                    _ => return Err(format!("expected ',' or ']' at {}", ptr)),
+                }
+            }
+            *ptr += 1;
+            Ok(Value::Array(res))
+        }
+        Some('{') => {
+            *ptr += 1;
+            let mut res = vec![];
+            skip_whitespace(chars, ptr);
+            if chars.get(*ptr) == Some(&'}') {
+                *ptr += 1;
+                return Ok(Value::Object(res));
+            }
+            loop {
+                skip_whitespace(chars, ptr);
+                let key = parse_string(chars, ptr)?;
+                skip_whitespace(chars, ptr);
+                if chars.get(*ptr) != Some(&':') {
+                    return Err(format!("expected ':' at {}", ptr));
+                }
+                *ptr += 1;
+                res.push((key, parse_value(chars, ptr, depth + 1)?));
+                skip_whitespace(chars, ptr);
+                match chars.get(*ptr) {
+                    Some(',') => *ptr += 1,
+                    Some('}') => break,
+                    _ => return Err(format!("expected ',' or '}}' at {}", ptr)),
+                }
+            }
+            *ptr += 1;
+            Ok(Value::Object(res))
+        }
+        Some(c) if *c == '-' || c.is_ascii_digit() => {
+            let start = *ptr;
+            while *ptr < chars.len() && "+-.eE0123456789".contains(chars[*ptr]) {
+                *ptr += 1;
+            }
+            let text: String = chars[start..*ptr].iter().collect();
+            match text.parse() {
+                Ok(v) => Ok(Value::Number(v)),
+                Err(_) => Err(format!("invalid number {:?}", text)),
+            }
+        }
+        _ => Err(format!("unexpected character at {}", ptr)),
+    }
+}
+
+fn parse_hex(chars: &[char], ptr: &mut usize) -> Result<u32, String> {
+    let digits: String = chars.iter().skip(*ptr).take(4).collect();
+    *ptr += 4;
+    match u32::from_str_radix(&digits, 16) {
+        Ok(v) if digits.len() == 4 => Ok(v),
+        _ => Err(format!("invalid escape {:?}", digits)),
+    }
+}
+
+fn parse_string(chars: &[char], ptr: &mut usize) -> Result<String, String> {
+    if chars.get(*ptr) != Some(&'"') {
+        return Err(format!("expected string at {}", ptr));
+    }
+    *ptr += 1;
+    let mut res = String::new();
+    loop {
+        let c = match chars.get(*ptr) {
+            Some(c) => *c,
+            None => return Err(String::from("unterminated string")),
+        };
+        *ptr += 1;
+        match c {
+            '"' => return Ok(res),
+            '\\' => {
+                let c = chars.get(*ptr).copied().unwrap_or_default();
+                *ptr += 1;
+                res.push(match c {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        // surrogate pairs arrive as two consecutive escapes
+                        let mut code = parse_hex(chars, ptr)?;
+                        if (0xd800..0xdc00).contains(&code) && chars.get(*ptr) == Some(&'\\') {
+                            *ptr += 2;
+                            let low = parse_hex(chars, ptr)?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                        }
+                        char::from_u32(code).unwrap_or('\u{fffd}')
+                    }
+                    c => c,
+                });
+            }
+            c => res.push(c),
+        }
+    }
+}
+
+/// Largest message accepted, far beyond any realistic document.
+pub const MAX_MESSAGE_LENGTH: usize = 8 << 20;
+
+/// Reads one message framed by a `Content-Length` header. Returns `None` once
+/// the input is closed. Malformed JSON yields an `InvalidData` error after the
+/// whole message has been consumed, so that reading may go on.
+pub fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(v) = line.strip_prefix("Content-Length:") {
+            length = v.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.unwrap_or_default();
+    if length > MAX_MESSAGE_LENGTH {
+        let err = format!("message of {} bytes exceeds the limit", length);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+    }
+    let mut buf = vec![0; length];
+    input.read_exact(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    match Value::parse(&text) {
+        Ok(v) => Ok(Some(v)),
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+    }
+}
+
+/// Writes one message preceded by its `Content-Length` header.
+pub fn write_message(output: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let text = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn parses_nested_values() {
+        let value = Value::parse(r#" {"a": [1, -2.5, true, null], "b": {"c": "d"}} "#).unwrap();
+        assert_eq!(value.get("a").as_array().len(), 4);
+        assert_eq!(value.get("a").as_array()[0].as_i64(), Some(1));
+        assert_eq!(value.get("a").as_array()[1], Value::Number(-2.5));
+        assert_eq!(value.get("a").as_array()[2].as_bool(), Some(true));
+        assert!(value.get("a").as_array()[3].is_null());
+        assert_eq!(value.get("b").get("c").as_str(), Some("d"));
+        assert!(value.get("missing").get("deeper").is_null());
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let value = Value::parse(r#""a\"b\\c\n\u00e9\ud83d\ude00""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"b\\c\né😀"));
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        for source in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"open",
+            "nul",
+            "1 2",
+            "\"\\u12\"",
+        ] {
+            assert!(Value::parse(source).is_err(), "accepted {:?}", source);
+        }
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Value::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Value::parse(&"{\"a\":".repeat(MAX_DEPTH + 1)).is_err());
+        // far too deep to recurse into, yet well below the size limit
+        let input = frame(&"[".repeat(1 << 20));
+        let err = read_message(&mut input.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn serializes_compactly() {
+        let value = Value::object(vec![
+            ("n", 3i64.into()),
+            ("f", Value::Number(0.5)),
+            ("s", "q\"\u{1}".into()),
+            ("a", vec![Value::Null, true.into()].into()),
+        ]);
+        let text = value.to_string();
+        assert_eq!(text, r#"{"n":3,"f":0.5,"s":"q\"\u0001","a":[null,true]}"#);
+        assert_eq!(Value::parse(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let input = format!("{}{}", frame("{\"id\":1}"), frame("[\"é\"]"));
+        let mut input = input.as_bytes();
+        let first = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(first.get("id").as_i64(), Some(1));
+        let second = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(second.as_array()[0].as_str(), Some("é"));
+        assert!(read_message(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn skips_past_malformed_messages() {
+        let input = format!("{}{}", frame("{bad"), frame("2"));
+        let mut input = input.as_bytes();
+        let err = read_message(&mut input).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let next = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(next.as_i64(), Some(2));
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let input = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE_LENGTH + 1);
+        let err = read_message(&mut input.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn writes_length_in_bytes() {
+        let mut output = vec![];
+        write_message(&mut output, &Value::from("é")).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), frame("\"é\""));
+    }
+}
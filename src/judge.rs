@@ -0,0 +1,378 @@
+//! Judging of submissions against test cases under resource limits.
+//!
+//! Every case is run with a budget of steps, where a step is a statement or a
+//! loop condition evaluation, and of wall clock time. Calls are also limited
+//! in depth, so that runaway recursion is reported instead of overflowing the
+//! interpreter's own stack.
+
+use std::time::{Duration, Instant};
+
+use crate::cases::{self, Case};
+use crate::json::Value;
+use crate::{Error, Frame, Hook, Program, Statement};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Accepted,
+    WrongAnswer,
+    TimeLimitExceeded,
+    RuntimeError,
+    CompileError,
+}
+
+impl Verdict {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Accepted => "AC",
+            Self::WrongAnswer => "WA",
+            Self::TimeLimitExceeded => "TLE",
+            Self::RuntimeError => "RE",
+            Self::CompileError => "CE",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Accepted => "Accepted",
+            Self::WrongAnswer => "Wrong Answer",
+            Self::TimeLimitExceeded => "Time Limit Exceeded",
+            Self::RuntimeError => "Runtime Error",
+            Self::CompileError => "Compile Error",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub steps: u64,
+    pub time: Duration,
+    pub depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: 10_000_000,
+            time: Duration::from_secs(1),
+            depth: 10_000,
+        }
+    }
+}
+
+/// Which limit stopped a program.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Exceeded {
+    Steps,
+    Time,
+    Depth,
+}
+
+struct Watchdog {
+    limits: Limits,
+    start: Instant,
+    steps: u64,
+    exceeded: Option<Exceeded>,
+}
+
+impl Watchdog {
+    // `is_multiple_of` would require Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn step(&mut self, line: usize) -> Result<(), Error> {
+        self.steps += 1;
+        if self.steps > self.limits.steps {
+            self.exceeded = Some(Exceeded::Steps);
+        }
+        // reading the clock on every step would dominate the run time
+        if self.steps % 1024 == 0 && self.start.elapsed() > self.limits.time {
+            self.exceeded = Some(Exceeded::Time);
+        }
+        match self.exceeded {
+            Some(_) => Err(Error::Interrupted { line }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Hook for Watchdog {
+    fn on_statement(&mut self, _frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        // loops are counted per condition instead
+        if let Statement::Loop { .. } = stmt {
+            return Ok(());
+        }
+        self.step(stmt.line())
+    }
+
+    fn on_condition(
+        &mut self,
+        _frames: &[Frame],
+        stmt: &Statement,
+        _value: i64,
+    ) -> Result<(), Error> {
+        match stmt {
+            Statement::Loop { line, .. } => self.step(*line),
+            _ => Ok(()),
+        }
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        if frames.len() <= self.limits.depth {
+            return Ok(());
+        }
+        self.exceeded = Some(Exceeded::Depth);
+        Err(Error::Interrupted {
+            line: frames.last().map_or(0, |v| v.line),
+        })
+    }
+}
+
+pub struct Judgement {
+    pub name: String,
+    pub verdict: Verdict,
+    /// Explanation of anything but an accepted case.
+    pub detail: String,
+    /// Variant and line of the error behind a runtime or compile error.
+    pub error: Option<(String, usize)>,
+    pub time: Duration,
+    pub steps: u64,
+}
+
+impl Judgement {
+    /// Reports a submission failing to load, for every case.
+    pub fn compile_error(case: &Case, err: &Error) -> Self {
+        Self {
+            name: case.name.clone(),
+            verdict: Verdict::CompileError,
+            detail: err.format(),
+            error: Some((variant(err), err.line())),
+            time: Duration::ZERO,
+            steps: 0,
+        }
+    }
+}
+
+/// Name of an error variant, without its fields.
+fn variant(err: &Error) -> String {
+    let debug = err.debug();
+    String::from(debug.split('(').next().unwrap_or_default())
+}
+
+/// Runs a program on one case and classifies the result.
+pub fn judge_case(prog: &Program, case: &Case, limits: Limits) -> Judgement {
+    let mut watchdog = Watchdog {
+        limits,
+        start: Instant::now(),
+        steps: 0,
+        exceeded: None,
+    };
+    let outcome = cases::run_case(prog, case, &mut watchdog);
+    // programs finishing late are caught here, between clock checks
+    if outcome.time > limits.time {
+        watchdog.exceeded.get_or_insert(Exceeded::Time);
+    }
+    let (verdict, detail, error) = match (watchdog.exceeded, outcome.result, &case.expected) {
+        (Some(Exceeded::Steps), _, _) => (
+            Verdict::TimeLimitExceeded,
+            format!("step limit of {} exceeded", limits.steps),
+            None,
+        ),
+        (Some(Exceeded::Time), _, _) => (
+            Verdict::TimeLimitExceeded,
+            format!("time limit of {} ms exceeded", limits.time.as_millis()),
+            None,
+        ),
+        (Some(Exceeded::Depth), _, _) => (
+            Verdict::RuntimeError,
+            format!("call depth limit of {} exceeded", limits.depth),
+            None,
+        ),
+        (None, Err(err), _) => (
+            Verdict::RuntimeError,
+            format!("{}: {}", variant(&err), err.format()),
+            Some((variant(&err), err.line())),
+        ),
+        (None, Ok(_), None) => (
+            Verdict::WrongAnswer,
+            format!("{}.out: cannot read file", case.name),
+            None,
+        ),
+        (None, Ok(_), Some(v)) if !cases::same_output(&outcome.stdout, v) => {
+            (Verdict::WrongAnswer, cases::diff(&outcome.stdout, v), None)
+        }
+        (None, Ok(_), Some(_)) => (Verdict::Accepted, String::new(), None),
+    };
+    Judgement {
+        name: case.name.clone(),
+        verdict,
+        detail,
+        error,
+        time: outcome.time,
+        steps: watchdog.steps,
+    }
+}
+
+/// Percentage of accepted cases.
+pub fn score(results: &[Judgement]) -> f64 {
+    let accepted = results
+        .iter()
+        .filter(|v| v.verdict == Verdict::Accepted)
+        .count();
+    match results.len() {
+        0 => 0.0,
+        all => accepted as f64 * 100.0 / all as f64,
+    }
+}
+
+/// The overall verdict is that of the first case not accepted.
+pub fn overall(results: &[Judgement]) -> Verdict {
+    results
+        .iter()
+        .map(|v| v.verdict)
+        .find(|v| *v != Verdict::Accepted)
+        .unwrap_or(Verdict::Accepted)
+}
+
+/// Summarizes a judged submission as JSON.
+pub fn report(program: &str, limits: Limits, results: &[Judgement]) -> Value {
+    let cases = results
+        .iter()
+        .map(|v| {
+            let (error, line) = match &v.error {
+                Some((name, line)) => (Value::from(name.as_str()), Value::from(line + 1)),
+                None => (Value::Null, Value::Null),
+            };
+            Value::object(vec![
+                ("name", Value::from(v.name.as_str())),
+                ("verdict", Value::from(v.verdict.code())),
+                ("detail", Value::from(v.detail.trim_end())),
+                ("error", error),
+                ("line", line),
+                ("timeMs", Value::Number(v.time.as_secs_f64() * 1000.0)),
+                ("steps", Value::from(v.steps as i64)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let accepted = results
+        .iter()
+        .filter(|v| v.verdict == Verdict::Accepted)
+        .count();
+    Value::object(vec![
+        ("program", Value::from(program)),
+        ("verdict", Value::from(overall(results).code())),
+        ("score", Value::Number(score(results))),
+        ("accepted", Value::from(accepted)),
+        ("total", Value::from(results.len())),
+        (
+            "limits",
+            Value::object(vec![
+                ("steps", Value::from(limits.steps as i64)),
+                ("timeMs", Value::from(limits.time.as_millis() as i64)),
+                ("depth", Value::from(limits.depth)),
+            ]),
+        ),
+        ("cases", Value::from(cases)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(source: &str) -> Program {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.load(source).unwrap();
+        prog
+    }
+
+    fn case(input: &str, expected: Option<&str>) -> Case {
+        Case {
+            name: String::from("1"),
+            input: String::from(input),
+            expected: expected.map(String::from),
+        }
+    }
+
+    const ECHO: &str =
+        "function main as\n    let a = scan\n    print a\n    return 0\nend function\n";
+
+    #[test]
+    fn accepts_matching_output() {
+        let result = judge_case(&load(ECHO), &case("5", Some("5\n")), Limits::default());
+        assert_eq!(result.verdict, Verdict::Accepted);
+        assert!(result.detail.is_empty());
+        assert!(result.error.is_none());
+        assert!(result.steps > 0);
+    }
+
+    #[test]
+    fn rejects_differing_or_missing_output() {
+        let result = judge_case(&load(ECHO), &case("5", Some("6")), Limits::default());
+        assert_eq!(result.verdict, Verdict::WrongAnswer);
+        assert!(result.detail.contains("expected \"6\", got \"5\""));
+        let result = judge_case(&load(ECHO), &case("5", None), Limits::default());
+        assert_eq!(result.verdict, Verdict::WrongAnswer);
+        assert_eq!(result.detail, "1.out: cannot read file");
+    }
+
+    #[test]
+    fn reports_runtime_errors_with_their_line() {
+        let result = judge_case(&load(ECHO), &case("", Some("")), Limits::default());
+        assert_eq!(result.verdict, Verdict::RuntimeError);
+        assert_eq!(result.error, Some((String::from("EndOfInput"), 1)));
+    }
+
+    #[test]
+    fn stops_programs_at_the_limits() {
+        let spin = "function main as\n    while 1 do\n    end while\n    return 0\nend function\n";
+        let limits = Limits {
+            steps: 100,
+            ..Limits::default()
+        };
+        let result = judge_case(&load(spin), &case("", Some("")), limits);
+        assert_eq!(result.verdict, Verdict::TimeLimitExceeded);
+        assert_eq!(result.detail, "step limit of 100 exceeded");
+        assert_eq!(result.steps, 101);
+
+        let recurse = "function f as\n    return f\nend function\nfunction main as\n    return f\nend function\n";
+        let limits = Limits {
+            depth: 50,
+            ..Limits::default()
+        };
+        let result = judge_case(&load(recurse), &case("", Some("")), limits);
+        assert_eq!(result.verdict, Verdict::RuntimeError);
+        assert_eq!(result.detail, "call depth limit of 50 exceeded");
+        assert!(result.error.is_none());
+    }
+
+    #[test]
+    fn summarizes_results() {
+        let prog = load(ECHO);
+        let results = vec![
+            judge_case(&prog, &case("1", Some("1")), Limits::default()),
+            judge_case(&prog, &case("2", Some("3")), Limits::default()),
+            judge_case(&prog, &case("", Some("")), Limits::default()),
+            judge_case(&prog, &case("4", Some("4")), Limits::default()),
+        ];
+        assert_eq!(score(&results), 50.0);
+        assert_eq!(overall(&results), Verdict::WrongAnswer);
+        assert_eq!(overall(&results[..1]), Verdict::Accepted);
+        assert_eq!(score(&[]), 0.0);
+
+        let report = report("echo.nh", Limits::default(), &results).to_string();
+        assert!(report.starts_with("{\"program\":\"echo.nh\",\"verdict\":\"WA\",\"score\":50,"));
+        assert!(report.contains("\"accepted\":2,\"total\":4,"));
+        assert!(report.contains("\"verdict\":\"RE\",\"detail\":"));
+        assert!(report.contains("\"error\":\"EndOfInput\",\"line\":2,"));
+    }
+
+    #[test]
+    fn reports_compile_errors_for_every_case() {
+        let mut prog = Program::new();
+        let err = prog.load("function main as\n").unwrap_err();
+        let result = Judgement::compile_error(&case("", None), &err);
+        assert_eq!(result.verdict, Verdict::CompileError);
+        assert_eq!(result.verdict.code(), "CE");
+        assert_eq!(result.verdict.name(), "Compile Error");
+        assert_eq!(result.error, Some((variant(&err), err.line())));
+    }
+}
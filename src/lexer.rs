@@ -0,0 +1,251 @@
+//! Lexical analysis, turning source code into typed tokens.
+//!
+//! Words are separated by ASCII whitespace, and a `#` starts a comment that
+//! runs to the end of the line. The lexer knows nothing about statements, so
+//! tooling may rely on it without going through the parser.
+
+use crate::Error;
+
+/// Kinds of lexemes found in Nhotyp source code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// Reserved words such as `let`, `while` or `and`.
+    Keyword,
+    /// Variable and function names.
+    Ident,
+    /// Integer constants, possibly signed.
+    Int,
+    /// Symbolic operators, including the `=` of assignments.
+    Operator,
+    /// A `#` and everything following it on the same line.
+    Comment,
+    /// The break between two lines.
+    Newline,
+}
+
+/// Position of a lexeme. Lines and columns are 0-based, and columns as well
+/// as lengths are counted in characters.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Lexeme {
+    pub kind: Kind,
+    pub text: String,
+    pub span: Span,
+}
+
+pub const KEYWORDS: &[&str] = &[
+    "and", "or", "xor", "not", "scan", "eof", "let", "if", "then", "while", "do", "function", "as",
+    "return", "end", "print", "eprint", "assert", "test",
+];
+
+pub const OPERATORS: &[&str] = &[
+    "+", "-", "*", "%", "/", "==", "<", ">", "<=", ">=", "!=", "=",
+];
+
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains(&word)
+}
+
+/// Splits source code into lines, accepting `\n`, `\r\n` and `\r` line
+/// endings alike and discarding a leading byte order mark.
+pub fn split_lines(source: &str) -> Vec<String> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let source = source.replace("\r\n", "\n").replace('\r', "\n");
+    source.split('\n').map(String::from).collect()
+}
+
+/// Tokenizes a whole source file, separating lines with `Kind::Newline`.
+pub fn tokenize(source: &str) -> Result<Vec<Lexeme>, Error> {
+    let lines = split_lines(source);
+    let mut res = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        res.extend(tokenize_line(line, i)?);
+        if i + 1 < lines.len() {
+            res.push(Lexeme {
+                kind: Kind::Newline,
+                text: String::from("\n"),
+                span: Span {
+                    line: i,
+                    col: line.chars().count(),
+                    len: 1,
+                },
+            });
+        }
+    }
+    Ok(res)
+}
+
+/// Tokenizes a single line, given its 0-based line number.
+pub fn tokenize_line(line: &str, line_no: usize) -> Result<Vec<Lexeme>, Error> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut res = vec![];
+    let mut ptr = 0;
+    while ptr < chars.len() {
+        if chars[ptr].is_ascii_whitespace() {
+            ptr += 1;
+            continue;
+        }
+        let start = ptr;
+        // comments swallow the rest of the line
+        if chars[ptr] == '#' {
+            ptr = chars.len();
+        } else {
+            while ptr < chars.len() && !chars[ptr].is_ascii_whitespace() && chars[ptr] != '#' {
+                ptr += 1;
+            }
+        }
+        let text: String = chars[start..ptr].iter().collect();
+        let kind = match text.starts_with('#') {
+            true => Kind::Comment,
+            false => classify(&text, line_no)?,
+        };
+        let span = Span {
+            line: line_no,
+            col: start,
+            len: ptr - start,
+        };
+        res.push(Lexeme { kind, text, span });
+    }
+    Ok(res)
+}
+
+/// Determines the kind of a single word, rejecting illegal ones.
+pub fn classify(word: &str, line: usize) -> Result<Kind, Error> {
+    if word.len() > 63 {
+        return Err(Error::TokenTooLong {
+            line,
+            value: word.len(),
+        });
+    }
+    let is_name = |c: char| c.is_ascii_lowercase() || c == '_';
+    let is_symbol = |c: char| "+-*%/<=>!".contains(c);
+    if OPERATORS.contains(&word) {
+        return Ok(Kind::Operator);
+    } else if !word.is_empty() && word.chars().all(is_name) {
+        return Ok(match is_keyword(word) {
+            true => Kind::Keyword,
+            false => Kind::Ident,
+        });
+    } else if word.parse::<i128>().is_ok() {
+        return Ok(Kind::Int);
+    }
+    // report the first character not allowed anywhere, or otherwise the first
+    // one not belonging with its predecessors
+    let legal = |c: char| is_name(c) || is_symbol(c) || c.is_ascii_digit();
+    let mut chars = word.chars();
+    let value = match word.chars().find(|c| !legal(*c)) {
+        Some(c) => c,
+        None => match chars.next() {
+            Some(c) if is_name(c) => chars.find(|c| !is_name(*c)).unwrap_or(c),
+            Some(c) if c.is_ascii_digit() => chars.find(|c| !c.is_ascii_digit()).unwrap_or(c),
+            Some(c) => chars.find(|c| !is_symbol(*c)).unwrap_or(c),
+            None => ' ',
+        },
+    };
+    Err(Error::IllegalChar { line, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(line: &str) -> Vec<(Kind, String)> {
+        let words = tokenize_line(line, 0).unwrap();
+        words.into_iter().map(|v| (v.kind, v.text)).collect()
+    }
+
+    #[test]
+    fn classifies_words() {
+        let words = kinds("let x_y = + -12 scan # done");
+        let expected = [
+            (Kind::Keyword, "let"),
+            (Kind::Ident, "x_y"),
+            (Kind::Operator, "="),
+            (Kind::Operator, "+"),
+            (Kind::Int, "-12"),
+            (Kind::Keyword, "scan"),
+            (Kind::Comment, "# done"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(k, v)| (*k, String::from(*v)))
+            .collect();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn comments_need_no_space() {
+        let words = kinds("print x#y z");
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[2], (Kind::Comment, String::from("#y z")));
+    }
+
+    #[test]
+    fn spans_count_characters() {
+        let words = tokenize_line("\tlet  ab = 1", 4).unwrap();
+        let spans: Vec<_> = words
+            .iter()
+            .map(|v| (v.span.line, v.span.col, v.span.len))
+            .collect();
+        assert_eq!(spans, vec![(4, 1, 3), (4, 6, 2), (4, 9, 1), (4, 11, 1)]);
+    }
+
+    #[test]
+    fn rejects_illegal_words() {
+        let err = tokenize_line("let aB = 1", 3).unwrap_err();
+        assert_eq!(
+            err,
+            Error::IllegalChar {
+                line: 3,
+                value: 'B'
+            }
+        );
+        let err = tokenize_line("let x = 1$", 0).unwrap_err();
+        assert_eq!(
+            err,
+            Error::IllegalChar {
+                line: 0,
+                value: '$'
+            }
+        );
+        let err = classify(&"a".repeat(64), 7).unwrap_err();
+        assert_eq!(err, Error::TokenTooLong { line: 7, value: 64 });
+        assert!(classify(&"a".repeat(63), 7).is_ok());
+    }
+
+    #[test]
+    fn splits_any_line_ending() {
+        let lines = split_lines("\u{feff}a\r\nb\rc\nd");
+        assert_eq!(lines, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn separates_lines_with_newlines() {
+        let words = tokenize("print x\n\nreturn x").unwrap();
+        let kinds: Vec<_> = words.iter().map(|v| v.kind).collect();
+        let expected = [
+            Kind::Keyword,
+            Kind::Ident,
+            Kind::Newline,
+            Kind::Newline,
+            Kind::Keyword,
+            Kind::Ident,
+        ];
+        assert_eq!(kinds, expected);
+        assert_eq!(
+            words[2].span,
+            Span {
+                line: 0,
+                col: 7,
+                len: 1
+            }
+        );
+        assert_eq!(words[4].span.line, 2);
+    }
+}
@@ -0,0 +1,2127 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::ops;
+
+pub mod cases;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod emit_c;
+pub mod emit_python;
+pub mod formatter;
+pub mod infix;
+pub mod json;
+pub mod judge;
+pub mod lexer;
+pub mod lint;
+pub mod lsp;
+pub mod profile;
+pub mod trace;
+mod transpile;
+
+pub use lexer::split_lines;
+use lexer::{Kind, Lexeme};
+
+///////////////////////////////////////////////////////////////////////////////
+// Error handling
+
+#[allow(clippy::enum_variant_names)]
+#[derive(PartialEq, Eq)]
+pub enum Error {
+    IllegalChar { line: usize, value: char },
+    TokenTooLong { line: usize, value: usize },
+    UnknownToken { line: usize, value: String },
+    MalformedAssign { line: usize },
+    MalformedCond { line: usize },
+    MalformedLoop { line: usize },
+    MalformedRet { line: usize },
+    MalformedFunc { line: usize },
+    MalformedEnd { line: usize },
+    MalformedAssert { line: usize },
+    MalformedTest { line: usize },
+    UnclosedBlock,
+    DuplicateToken { line: usize, value: String },
+    WildStatement { line: usize },
+    WildFunction { line: usize },
+    WildTest { line: usize },
+    MisplacedRet { line: usize },
+    UndeclaredToken { line: usize, value: String },
+    BadExpression { line: usize },
+    InputError { line: usize, value: String },
+    EndOfInput { line: usize },
+    NativeError { line: usize, value: String },
+    OutOfRange { line: usize, value: String },
+    BadArguments { line: usize, value: String },
+    AssertionFailed { line: usize, value: String },
+    Interrupted { line: usize },
+}
+
+impl Error {
+    pub fn debug(&self) -> String {
+        match self {
+            Self::IllegalChar { line, value } => format!("IllegalChar({}, {:?})", line, value),
+            Self::TokenTooLong { line, value } => format!("TokenTooLong({}, {})", line, value),
+            Self::UnknownToken { line, value } => format!("UnknownToken({}, {:?})", line, value),
+            Self::MalformedAssign { line } => format!("MalformedAssign({})", line),
+            Self::MalformedCond { line } => format!("MalformedCond({})", line),
+            Self::MalformedLoop { line } => format!("MalformedLoop({})", line),
+            Self::MalformedRet { line } => format!("MalformedRet({})", line),
+            Self::MalformedFunc { line } => format!("MalformedFunc({})", line),
+            Self::MalformedEnd { line } => format!("MalformedEnd({})", line),
+            Self::MalformedAssert { line } => format!("MalformedAssert({})", line),
+            Self::MalformedTest { line } => format!("MalformedTest({})", line),
+            Self::UnclosedBlock => String::from("UnclosedBlock"),
+            Self::DuplicateToken { line, value } => {
+                format!("DuplicateToken({}, {:?})", line, value)
+            }
+            Self::WildStatement { line } => format!("WildStatement({})", line),
+            Self::WildFunction { line } => format!("WildFunction({})", line),
+            Self::WildTest { line } => format!("WildTest({})", line),
+            Self::MisplacedRet { line } => format!("MisplacedRet({})", line),
+            Self::UndeclaredToken { line, value } => {
+                format!("UndeclaredToken({}, {})", line, value)
+            }
+            Self::BadExpression { line } => format!("BadExpression({})", line),
+            Self::InputError { line, value } => format!("InputError({}, {:?})", line, value),
+            Self::EndOfInput { line } => format!("EndOfInput({})", line),
+            Self::NativeError { line, value } => format!("NativeError({}, {:?})", line, value),
+            Self::OutOfRange { line, value } => format!("OutOfRange({}, {})", line, value),
+            Self::BadArguments { line, value } => format!("BadArguments({}, {:?})", line, value),
+            Self::AssertionFailed { line, value } => {
+                format!("AssertionFailed({}, {:?})", line, value)
+            }
+            Self::Interrupted { line } => format!("Interrupted({})", line),
+        }
+    }
+
+    pub fn format(&self) -> String {
+        match self {
+            Self::IllegalChar { value, .. } => {
+                format!("unexpected character {:?}", value)
+            }
+            Self::TokenTooLong { value, .. } => {
+                format!("token length exceeded ({} of 63)", value)
+            }
+            Self::UnknownToken { value, .. } => {
+                format!("unexpected statement token {:?}", value)
+            }
+            Self::MalformedAssign { .. } => String::from("malformed assignment statement"),
+            Self::MalformedCond { .. } => String::from("malformed conditional statement"),
+            Self::MalformedLoop { .. } => String::from("malformed loop statement"),
+            Self::MalformedRet { .. } => String::from("malformed return statement"),
+            Self::MalformedFunc { .. } => String::from("bad function definition"),
+            Self::MalformedEnd { .. } => String::from("illegal code block end"),
+            Self::MalformedAssert { .. } => String::from("malformed assertion statement"),
+            Self::MalformedTest { .. } => String::from("bad test definition"),
+            Self::UnclosedBlock => String::from("code block unclosed"),
+            Self::DuplicateToken { value, .. } => {
+                format!("conflict token {:?}", value)
+            }
+            Self::WildStatement { .. } => String::from("statements should appear in functions"),
+            Self::WildFunction { .. } => String::from("function should not appear in functions"),
+            Self::WildTest { .. } => String::from("test should not appear in functions"),
+            Self::MisplacedRet { .. } => String::from("always return at end of function"),
+            Self::UndeclaredToken { value, .. } => {
+                format!("token {:?} undeclared", value)
+            }
+            Self::BadExpression { .. } => String::from("expression having misplaced tokens"),
+            Self::InputError { value, .. } => {
+                format!("invalid input {:?}", value)
+            }
+            Self::EndOfInput { .. } => String::from("unexpected end of input"),
+            Self::NativeError { value, .. } => {
+                format!("native function failed: {}", value)
+            }
+            Self::OutOfRange { value, .. } => {
+                format!("value {} does not fit in 64 bits", value)
+            }
+            Self::BadArguments { value, .. } => {
+                format!("wrong number of arguments for {:?}", value)
+            }
+            Self::AssertionFailed { value, .. } => {
+                format!("assertion {}", value)
+            }
+            Self::Interrupted { .. } => String::from("execution interrupted"),
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            Self::IllegalChar { line, .. } => *line,
+            Self::TokenTooLong { line, .. } => *line,
+            Self::UnknownToken { line, .. } => *line,
+            Self::MalformedAssign { line, .. } => *line,
+            Self::MalformedCond { line, .. } => *line,
+            Self::MalformedLoop { line, .. } => *line,
+            Self::MalformedRet { line, .. } => *line,
+            Self::MalformedFunc { line, .. } => *line,
+            Self::MalformedEnd { line, .. } => *line,
+            Self::MalformedAssert { line, .. } => *line,
+            Self::MalformedTest { line, .. } => *line,
+            Self::UnclosedBlock => 0,
+            Self::DuplicateToken { line, .. } => *line,
+            Self::WildStatement { line, .. } => *line,
+            Self::WildFunction { line, .. } => *line,
+            Self::WildTest { line, .. } => *line,
+            Self::MisplacedRet { line, .. } => *line,
+            Self::UndeclaredToken { line, .. } => *line,
+            Self::BadExpression { line, .. } => *line,
+            Self::InputError { line, .. } => *line,
+            Self::EndOfInput { line, .. } => *line,
+            Self::NativeError { line, .. } => *line,
+            Self::OutOfRange { line, .. } => *line,
+            Self::BadArguments { line, .. } => *line,
+            Self::AssertionFailed { line, .. } => *line,
+            Self::Interrupted { line, .. } => *line,
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.debug())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format())
+    }
+}
+
+impl StdError for Error {}
+
+///////////////////////////////////////////////////////////////////////////////
+// Tokens and Expressions
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Token {
+    pub value: String,
+}
+
+impl Token {
+    fn from(lexeme: &Lexeme) -> Self {
+        Self {
+            value: String::from(&lexeme.text),
+        }
+    }
+
+    /// Validates a function or variable name given outside of source code.
+    fn from_name(line: usize, s: &str) -> Result<Self, Error> {
+        match lexer::classify(s, line)? {
+            Kind::Ident | Kind::Keyword => Ok(Self {
+                value: String::from(s),
+            }),
+            _ => Err(Error::IllegalChar {
+                line,
+                value: s.chars().next().unwrap_or(' '),
+            }),
+        }
+    }
+}
+
+impl Clone for Token {
+    fn clone(&self) -> Self {
+        Self {
+            value: String::from(&self.value),
+        }
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.value)
+    }
+}
+
+pub struct Expr {
+    pub tokens: Vec<Token>,
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_fmt(format_args!("{:?}", self.tokens))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Statements and Nodes
+
+pub enum Statement {
+    Assign {
+        var: Token,
+        expr: Expr,
+        line: usize,
+    },
+    Cond {
+        expr: Expr,
+        child: Node,
+        line: usize,
+    },
+    Loop {
+        expr: Expr,
+        child: Node,
+        line: usize,
+    },
+    Print {
+        expr: Expr,
+        line: usize,
+    },
+    Eprint {
+        expr: Expr,
+        line: usize,
+    },
+    Ret {
+        expr: Expr,
+        line: usize,
+    },
+    Func {
+        name: Token,
+        params: Vec<Token>,
+        child: Node,
+        line: usize,
+    },
+    Assert {
+        expr: Expr,
+        line: usize,
+    },
+    Test {
+        name: Token,
+        child: Node,
+        line: usize,
+    },
+}
+
+impl Statement {
+    pub fn line(&self) -> usize {
+        *match self {
+            Self::Assign { line, .. } => line,
+            Self::Cond { line, .. } => line,
+            Self::Loop { line, .. } => line,
+            Self::Print { line, .. } => line,
+            Self::Eprint { line, .. } => line,
+            Self::Ret { line, .. } => line,
+            Self::Func { line, .. } => line,
+            Self::Assert { line, .. } => line,
+            Self::Test { line, .. } => line,
+        }
+    }
+}
+
+impl fmt::Debug for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Assign { var, expr, line } => {
+                f.write_fmt(format_args!("let({:?} <- {:?} @ {})", var, expr, line))
+            }
+            Self::Cond { expr, child, line } => {
+                f.write_fmt(format_args!("if({:?} => {:?} @ {})", expr, child, line))
+            }
+            Self::Loop { expr, child, line } => {
+                f.write_fmt(format_args!("while({:?} => {:?} @ {})", expr, child, line))
+            }
+            Self::Print { expr, line } => f.write_fmt(format_args!("print({:?} @ {})", expr, line)),
+            Self::Eprint { expr, line } => {
+                f.write_fmt(format_args!("eprint({:?} @ {})", expr, line))
+            }
+            Self::Ret { expr, line } => f.write_fmt(format_args!("ret({:?} @ {})", expr, line)),
+            Self::Func {
+                name,
+                params,
+                child,
+                line,
+            } => f.write_fmt(format_args!(
+                "def({:?} -> {:?} => {:?} @ {})",
+                name, params, child, line
+            )),
+            Self::Assert { expr, line } => {
+                f.write_fmt(format_args!("assert({:?} @ {})", expr, line))
+            }
+            Self::Test { name, child, line } => {
+                f.write_fmt(format_args!("test({:?} => {:?} @ {})", name, child, line))
+            }
+        }
+    }
+}
+
+pub struct Node {
+    pub stmts: Vec<Statement>,
+    /// The line of the `end` closing the block, or the last line of the
+    /// source for the top level.
+    pub end: usize,
+    /// Full-line comments preceding each statement, one entry per statement,
+    /// with empty strings standing for blank lines. Only collected by
+    /// lossless parsing, and left empty otherwise, as are the fields below.
+    pub comments: Vec<Vec<String>>,
+    /// Comment at the end of the line of each statement, if any.
+    pub remarks: Vec<Option<String>>,
+    /// Full-line comments and blank lines after the last statement.
+    pub trailing: Vec<String>,
+    /// Comment at the end of the `end` line closing the block.
+    pub end_remark: Option<String>,
+}
+
+impl Node {
+    /// Lines of all statements, nested ones included, in source order.
+    pub fn stmt_lines(&self) -> Vec<usize> {
+        let mut res = vec![];
+        for stmt in &self.stmts {
+            res.push(stmt.line());
+            match stmt {
+                Statement::Cond { child, .. }
+                | Statement::Loop { child, .. }
+                | Statement::Func { child, .. }
+                | Statement::Test { child, .. } => res.extend(child.stmt_lines()),
+                _ => (),
+            }
+        }
+        res
+    }
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_fmt(format_args!("{:?}", self.stmts))
+    }
+}
+
+/// Language variants accepted by the parser.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Dialect {
+    /// The language exactly as specified.
+    #[default]
+    Standard,
+    /// Relaxes the specification for convenience, e.g. `print` accepts
+    /// arbitrary expressions instead of variable names only.
+    Extended,
+}
+
+struct State<'a> {
+    lines: &'a [String],
+    ptr: usize,
+    dialect: Dialect,
+    lossless: bool,
+}
+
+type StmtParseResult = Result<Statement, Error>;
+
+fn parse_name(state: &State, lexeme: &Lexeme) -> Result<Token, Error> {
+    match lexeme.kind {
+        Kind::Ident | Kind::Keyword => Ok(Token::from(lexeme)),
+        _ => Err(Error::IllegalChar {
+            line: state.ptr - 1,
+            value: lexeme.text.chars().next().unwrap_or(' '),
+        }),
+    }
+}
+
+fn parse_expr(words: &[Lexeme]) -> Expr {
+    Expr {
+        tokens: words.iter().map(Token::from).collect(),
+    }
+}
+
+fn parse_stmt_assign(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // let <variable> = <expression>
+    if words.len() < 4 || words[2].text != "=" {
+        return Err(Error::MalformedAssign {
+            line: state.ptr - 1,
+        });
+    }
+    Ok(Statement::Assign {
+        var: parse_name(state, &words[1])?,
+        expr: parse_expr(&words[3..]),
+        line: state.ptr - 1,
+    })
+}
+
+fn parse_stmt_cond(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // if <expression> then
+    //     <code block>
+    // end if
+    let len = words.len();
+    let line = state.ptr - 1;
+    if words.len() < 3 || words[len - 1].text != "then" {
+        return Err(Error::MalformedCond { line });
+    }
+    // get child node
+    Ok(Statement::Cond {
+        expr: parse_expr(&words[1..len - 1]),
+        child: parse_node(state, "if")?,
+        line,
+    })
+}
+
+fn parse_stmt_loop(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // while <expression> do
+    //     <code block>
+    // end while
+    let len = words.len();
+    let line = state.ptr - 1;
+    if words.len() < 3 || words[len - 1].text != "do" {
+        return Err(Error::MalformedLoop { line });
+    }
+    // get child node
+    Ok(Statement::Loop {
+        expr: parse_expr(&words[1..len - 1]),
+        child: parse_node(state, "while")?,
+        line,
+    })
+}
+
+fn parse_print_args(state: &mut State, words: &[Lexeme]) -> Result<Expr, Error> {
+    let mut tokens = vec![];
+    for word in &words[1..] {
+        tokens.push(match state.dialect {
+            Dialect::Standard => parse_name(state, word)?,
+            Dialect::Extended => Token::from(word),
+        });
+    }
+    Ok(Expr { tokens })
+}
+
+fn parse_stmt_print(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // print <var1> <var2> ... <varn>
+    // print <expr1> <expr2> ... <exprn> (extended dialect)
+    // allows 0 variables
+    Ok(Statement::Print {
+        expr: parse_print_args(state, words)?,
+        line: state.ptr - 1,
+    })
+}
+
+fn parse_stmt_eprint(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // eprint <var1> <var2> ... <varn>
+    // same as print, but writes to the error stream
+    Ok(Statement::Eprint {
+        expr: parse_print_args(state, words)?,
+        line: state.ptr - 1,
+    })
+}
+
+fn parse_stmt_ret(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // return <expression>
+    if words.len() < 2 {
+        return Err(Error::MalformedRet {
+            line: state.ptr - 1,
+        });
+    }
+    Ok(Statement::Ret {
+        expr: parse_expr(&words[1..]),
+        line: state.ptr - 1,
+    })
+}
+
+fn parse_stmt_func(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // function <name> <param1> <param2> ... <paramn> as
+    //     <code block>
+    // end function
+    let len = words.len();
+    let line = state.ptr - 1;
+    if words.len() < 3 || words[len - 1].text != "as" {
+        return Err(Error::MalformedFunc { line });
+    }
+    // parse parameters
+    let name = parse_name(state, &words[1])?;
+    let mut params = vec![];
+    for word in &words[2..len - 1] {
+        let token = parse_name(state, word)?;
+        if lexer::is_keyword(&token.value) {
+            return Err(Error::DuplicateToken {
+                line,
+                value: token.value,
+            });
+        }
+        params.push(token);
+    }
+    // too many parameters
+    if params.len() > 16 {
+        return Err(Error::MalformedFunc { line });
+    }
+    // get child node
+    Ok(Statement::Func {
+        name,
+        params,
+        child: parse_node(state, "function")?,
+        line,
+    })
+}
+
+fn parse_stmt_assert(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // assert <expression>
+    if words.len() < 2 {
+        return Err(Error::MalformedAssert {
+            line: state.ptr - 1,
+        });
+    }
+    Ok(Statement::Assert {
+        expr: parse_expr(&words[1..]),
+        line: state.ptr - 1,
+    })
+}
+
+fn parse_stmt_test(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    // test <name> as
+    //     <code block>
+    // end test
+    let line = state.ptr - 1;
+    if words.len() != 3 || words[2].text != "as" {
+        return Err(Error::MalformedTest { line });
+    }
+    Ok(Statement::Test {
+        name: parse_name(state, &words[1])?,
+        child: parse_node(state, "test")?,
+        line,
+    })
+}
+
+fn parse_stmt(state: &mut State, words: &[Lexeme]) -> StmtParseResult {
+    match words[0].text.as_str() {
+        "let" => parse_stmt_assign(state, words),
+        "if" => parse_stmt_cond(state, words),
+        "while" => parse_stmt_loop(state, words),
+        "print" => parse_stmt_print(state, words),
+        "eprint" => parse_stmt_eprint(state, words),
+        "return" => parse_stmt_ret(state, words),
+        "function" => parse_stmt_func(state, words),
+        "assert" => parse_stmt_assert(state, words),
+        "test" => parse_stmt_test(state, words),
+        _ => Err(Error::UnknownToken {
+            line: state.ptr - 1,
+            value: String::from(&words[0].text),
+        }),
+    }
+}
+
+fn parse_node(state: &mut State, term: &str) -> Result<Node, Error> {
+    let mut stmts = vec![];
+    let mut comments = vec![];
+    let mut remarks = vec![];
+    let mut pending = vec![];
+    let mut end_remark = None;
+    let mut gracefully_ended = term.is_empty();
+    let mut end = state.lines.len().saturating_sub(1);
+    // splitting words here to check for terminations
+    while state.ptr < state.lines.len() {
+        let mut words = lexer::tokenize_line(&state.lines[state.ptr], state.ptr)?;
+        state.ptr += 1;
+        // keep full-line comments and blank lines for the next statement if
+        // asked to, and comments ending a line for the statement on it
+        let mut remark = None;
+        if state.lossless {
+            match words.as_slice() {
+                [] => pending.push(String::new()),
+                [word] if word.kind == Kind::Comment => pending.push(String::from(&word.text)),
+                [.., word] if word.kind == Kind::Comment => remark = Some(String::from(&word.text)),
+                _ => (),
+            }
+        }
+        // eradicate comments and check if is empty line
+        words.retain(|w| w.kind != Kind::Comment);
+        if words.is_empty() {
+            continue;
+        }
+        // 'end' statement triggers code block close
+        if words[0].text == "end" {
+            if words.len() == 2 && words[1].text == term {
+                gracefully_ended = true;
+                end = state.ptr - 1;
+                end_remark = remark;
+                break;
+            }
+            return Err(Error::MalformedEnd {
+                line: state.ptr - 1,
+            });
+        }
+        // send statement to corresponding parser
+        if state.lossless {
+            comments.push(std::mem::take(&mut pending));
+            remarks.push(remark);
+        }
+        stmts.push(parse_stmt(state, &words)?);
+    }
+    // check if block is unterminated
+    if !gracefully_ended {
+        return Err(Error::UnclosedBlock);
+    }
+    // done node parsing
+    Ok(Node {
+        stmts,
+        end,
+        comments,
+        remarks,
+        trailing: pending,
+        end_remark,
+    })
+}
+
+/// Parses complete source code into its top-level statements.
+fn parse_program(lines: &[String], dialect: Dialect) -> Result<Node, Error> {
+    let mut state = State {
+        lines,
+        ptr: 0,
+        dialect,
+        lossless: false,
+    };
+    parse_node(&mut state, "")
+}
+
+/// Parses source code into a tree of statements, without defining or
+/// checking anything beyond syntax. Comments are discarded.
+pub fn parse(source: &str, dialect: Dialect) -> Result<Node, Error> {
+    parse_program(&split_lines(source), dialect)
+}
+
+/// Same as `parse`, but attaches full-line comments and blank lines to the
+/// statement (or function) following them, and comments ending a line to the
+/// statement on it, as needed by tools that re-emit source code.
+pub fn parse_lossless(source: &str, dialect: Dialect) -> Result<Node, Error> {
+    let mut lines = split_lines(source);
+    // what follows the final line break is no blank line of its own
+    if lines.last().is_some_and(|v| v.is_empty()) {
+        lines.pop();
+    }
+    let mut state = State {
+        lines: &lines,
+        ptr: 0,
+        dialect,
+        lossless: true,
+    };
+    parse_node(&mut state, "")
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Variables
+
+const VARIABLE_LIMIT: i128 = 0x1_0000_0000_0000;
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+struct Variable {
+    data: i128,
+}
+
+impl Variable {
+    fn from(val: i128) -> Self {
+        let mut data = val;
+        if data > 0 {
+            data &= VARIABLE_LIMIT - 1;
+        }
+        Self { data }
+    }
+
+    /// The value as handed over to the host. Only negative values, which are
+    /// never wrapped, can exceed the range of `i64`.
+    fn to_host(self, line: usize) -> Result<i64, Error> {
+        i64::try_from(self.data).map_err(|_| Error::OutOfRange {
+            line,
+            value: self.data.to_string(),
+        })
+    }
+
+    /// The value as reported to hooks, clamped to the range of `i64`.
+    fn clamped(self) -> i64 {
+        self.data.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+}
+
+impl ops::Add for Variable {
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        Self::from(self.data + other.data)
+    }
+}
+
+impl ops::Sub for Variable {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self::Output {
+        Self::from(self.data - other.data)
+    }
+}
+
+impl ops::Mul for Variable {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self::Output {
+        Self::from(self.data * other.data)
+    }
+}
+
+impl ops::Rem for Variable {
+    type Output = Self;
+    fn rem(self, other: Self) -> Self::Output {
+        let a = self.data;
+        let b = other.data.abs();
+        if b == 0 {
+            return Self::from(0);
+        }
+        Self::from(match a > 0 {
+            true => a % b,
+            false => (b - (-a) % b) % b,
+        })
+    }
+}
+
+impl ops::Div for Variable {
+    type Output = Self;
+    fn div(self, other: Self) -> Self::Output {
+        let b = other.data.abs();
+        if b == 0 {
+            return Self::from(0);
+        }
+        Self::from((self - self % other).data / b)
+    }
+}
+
+impl ops::BitAnd for Variable {
+    type Output = bool;
+    fn bitand(self, other: Self) -> bool {
+        self.data != 0 && other.data != 0
+    }
+}
+
+impl ops::BitOr for Variable {
+    type Output = bool;
+    fn bitor(self, other: Self) -> bool {
+        self.data != 0 || other.data != 0
+    }
+}
+
+impl ops::BitXor for Variable {
+    type Output = bool;
+    fn bitxor(self, other: Self) -> bool {
+        (self.data != 0) ^ (other.data != 0)
+    }
+}
+
+impl ops::Not for Variable {
+    type Output = bool;
+    fn not(self) -> bool {
+        self.data == 0
+    }
+}
+
+impl fmt::Debug for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_fmt(format_args!("{:?}", self.data))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Input and output
+
+/// Channels through which a running program talks to the outside world.
+pub trait Io {
+    /// Reads one line of input, or `None` once input is exhausted.
+    fn read_line(&mut self) -> Option<String>;
+    /// Writes to the output stream, where `print` goes.
+    fn write_out(&mut self, s: &str);
+    /// Writes to the error stream, where `eprint` goes.
+    fn write_err(&mut self, s: &str);
+}
+
+/// Process standard input, output and error.
+#[derive(Default)]
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+
+    fn write_out(&mut self, s: &str) {
+        let mut stdout = std::io::stdout();
+        stdout
+            .write_all(s.as_bytes())
+            .expect("unable to write stdout");
+        stdout.flush().expect("unable to flush stdout");
+    }
+
+    fn write_err(&mut self, s: &str) {
+        let mut stderr = std::io::stderr();
+        stderr
+            .write_all(s.as_bytes())
+            .expect("unable to write stderr");
+    }
+}
+
+/// Process standard output and error, with input taken from a fixed string
+/// rather than standard input.
+pub struct RedirectIo {
+    input: VecDeque<String>,
+}
+
+impl RedirectIo {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.lines().map(String::from).collect(),
+        }
+    }
+}
+
+impl Io for RedirectIo {
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+
+    fn write_out(&mut self, s: &str) {
+        StdIo.write_out(s);
+    }
+
+    fn write_err(&mut self, s: &str) {
+        StdIo.write_err(s);
+    }
+}
+
+/// In-memory channels, reading from a fixed input and collecting output.
+#[derive(Default)]
+pub struct BufferIo {
+    input: VecDeque<String>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl BufferIo {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.lines().map(String::from).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Io for BufferIo {
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+
+    fn write_out(&mut self, s: &str) {
+        self.stdout.push_str(s);
+    }
+
+    fn write_err(&mut self, s: &str) {
+        self.stderr.push_str(s);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Program execution
+
+struct Function {
+    params: Vec<Token>,
+    root: Node,
+    line: usize,
+}
+
+/// Signature of host-defined functions. Arguments arrive in call order and an
+/// `Err` message aborts the running program with `Error::NativeError`.
+pub type NativeFn = dyn Fn(&[i64]) -> Result<i64, String>;
+
+struct Native {
+    arity: usize,
+    func: Box<NativeFn>,
+}
+
+/// A set of Nhotyp functions, optionally extended with host-defined natives.
+#[derive(Default)]
+pub struct Program {
+    funcs: HashMap<Token, Function>,
+    // in order of definition, taking no parameters and returning nothing
+    tests: Vec<(Token, Function)>,
+    natives: HashMap<Token, Native>,
+    dialect: Dialect,
+    raw: bool,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the dialect used by subsequent calls to `load`.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    /// Omits the decorative `print` prefix and `scan` prompt, so that output
+    /// consists of nothing but space-separated values.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.raw = raw;
+    }
+
+    /// Registers a Rust closure callable from Nhotyp code as `name`, taking
+    /// exactly `arity` parameters. Names follow the same rules as functions
+    /// and may not conflict with keywords or other functions.
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, func: F) -> Result<(), Error>
+    where
+        F: Fn(&[i64]) -> Result<i64, String> + 'static,
+    {
+        let token = Token::from_name(0, name)?;
+        if lexer::is_keyword(&token.value) || self.contains(&token) {
+            return Err(Error::DuplicateToken {
+                line: 0,
+                value: token.value,
+            });
+        }
+        if arity > 16 {
+            return Err(Error::MalformedFunc { line: 0 });
+        }
+        let func = Box::new(func);
+        self.natives.insert(token, Native { arity, func });
+        Ok(())
+    }
+
+    /// Parses Nhotyp source and adds its functions to the program.
+    pub fn load(&mut self, source: &str) -> Result<(), Error> {
+        let node = parse_program(&split_lines(source), self.dialect)?;
+        // check for wild statements at global scope
+        for stmt in node.stmts {
+            if let Statement::Func { .. } | Statement::Test { .. } = stmt {
+                self.define(stmt)?;
+            } else {
+                return Err(Error::WildStatement { line: stmt.line() });
+            }
+        }
+        Ok(())
+    }
+
+    /// Invokes the `main` function and returns its result.
+    pub fn execute(&self) -> Result<i64, Error> {
+        self.execute_with(&mut StdIo)
+    }
+
+    /// Same as `execute`, but communicating through the given channels.
+    pub fn execute_with(&self, io: &mut dyn Io) -> Result<i64, Error> {
+        self.call_with(io, "main", vec![])
+    }
+
+    /// Same as `execute_with`, additionally reporting progress to a hook.
+    pub fn execute_hooked(&self, io: &mut dyn Io, hook: &mut dyn Hook) -> Result<i64, Error> {
+        self.run(io, Some(hook), "main", vec![])
+    }
+
+    /// Invokes any function of the program, user-defined or native, with the
+    /// given arguments. The argument count must match the function's arity.
+    pub fn call(&self, name: &str, args: Vec<i64>) -> Result<i64, Error> {
+        self.call_with(&mut StdIo, name, args)
+    }
+
+    /// Same as `call`, but communicating through the given channels.
+    pub fn call_with(&self, io: &mut dyn Io, name: &str, args: Vec<i64>) -> Result<i64, Error> {
+        self.run(io, None, name, args)
+    }
+
+    /// Names of all tests, in order of definition.
+    pub fn tests(&self) -> Vec<&str> {
+        self.tests.iter().map(|(k, _)| k.value.as_str()).collect()
+    }
+
+    /// Runs a single test, which passes unless it ends in an error such as a
+    /// failed assertion.
+    pub fn run_test(&self, io: &mut dyn Io, name: &str) -> Result<(), Error> {
+        let (token, test) = match self.tests.iter().find(|(k, _)| k.value == name) {
+            Some(v) => v,
+            None => {
+                return Err(Error::UndeclaredToken {
+                    line: 0,
+                    value: String::from(name),
+                })
+            }
+        };
+        let mut ctx = Context::new(io, None);
+        ctx.frames.push(Frame {
+            name: String::from(&token.value),
+            line: test.line,
+            scope: HashMap::new(),
+        });
+        let mut instance = RunInstance {
+            prog: self,
+            ctx: &mut ctx,
+        };
+        exec_node(&mut instance, &test.root)
+    }
+
+    fn run<'a>(
+        &self,
+        io: &'a mut dyn Io,
+        hook: Option<&'a mut dyn Hook>,
+        name: &str,
+        args: Vec<i64>,
+    ) -> Result<i64, Error> {
+        let token = Token::from_name(0, name)?;
+        match self.arity(&token) {
+            Some(cnt) if cnt == args.len() => (),
+            Some(_) => {
+                return Err(Error::BadArguments {
+                    line: 0,
+                    value: token.value,
+                })
+            }
+            None => {
+                return Err(Error::UndeclaredToken {
+                    line: 0,
+                    value: token.value,
+                })
+            }
+        }
+        let params = args
+            .into_iter()
+            .map(|v| Variable::from(v as i128))
+            .collect();
+        let mut ctx = Context::new(io, hook);
+        call_function(self, &mut ctx, &token, params, 0)?.to_host(0)
+    }
+
+    fn define(&mut self, stmt: Statement) -> Result<(), Error> {
+        match stmt {
+            Statement::Func {
+                name,
+                params,
+                child,
+                line,
+            } => {
+                if lexer::is_keyword(&name.value) || self.contains(&name) {
+                    return Err(Error::DuplicateToken {
+                        line,
+                        value: name.value,
+                    });
+                }
+                let root = child;
+                self.funcs.insert(name, Function { params, root, line });
+            }
+            Statement::Test { name, child, line } => {
+                // tests live apart from functions, so only clash among themselves
+                if self.tests.iter().any(|(k, _)| *k == name) {
+                    return Err(Error::DuplicateToken {
+                        line,
+                        value: name.value,
+                    });
+                }
+                let params = vec![];
+                let root = child;
+                self.tests.push((name, Function { params, root, line }));
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn contains(&self, token: &Token) -> bool {
+        self.funcs.contains_key(token) || self.natives.contains_key(token)
+    }
+
+    fn arity(&self, token: &Token) -> Option<usize> {
+        if let Some(func) = self.funcs.get(token) {
+            return Some(func.params.len());
+        }
+        self.natives.get(token).map(|native| native.arity)
+    }
+}
+
+/// One active call of a user-defined function.
+pub struct Frame {
+    pub name: String,
+    /// The line of the statement being executed by this call.
+    pub line: usize,
+    scope: HashMap<Token, Variable>,
+}
+
+impl Frame {
+    /// Variables in scope, sorted by name, with values clamped like those
+    /// reported to hooks.
+    pub fn variables(&self) -> Vec<(&str, i64)> {
+        let mut res: Vec<_> = self
+            .scope
+            .iter()
+            .map(|(k, v)| (k.value.as_str(), v.clamped()))
+            .collect();
+        res.sort_unstable();
+        res
+    }
+
+    pub fn get(&self, name: &str) -> Option<i64> {
+        let token = Token {
+            value: String::from(name),
+        };
+        self.scope.get(&token).map(|v| v.clamped())
+    }
+}
+
+/// Observer of a running program, such as a debugger. Each method receives
+/// the call stack, innermost call last, and may abort execution by returning
+/// an error. Values below `i64::MIN` are reported as `i64::MIN`.
+pub trait Hook {
+    /// Called before each statement, including the final `return`.
+    fn on_statement(&mut self, _frames: &[Frame], _stmt: &Statement) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called with the value of an `if` or `while` condition, the latter on
+    /// every evaluation.
+    fn on_condition(
+        &mut self,
+        _frames: &[Frame],
+        _stmt: &Statement,
+        _value: i64,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called after a variable has been assigned, with its previous value.
+    fn on_assign(
+        &mut self,
+        _frames: &[Frame],
+        _var: &str,
+        _old: Option<i64>,
+        _new: i64,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called once a function has been entered and its parameters bound.
+    fn on_call(&mut self, _frames: &[Frame]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called with a function's result, just before its frame is left.
+    fn on_return(&mut self, _frames: &[Frame], _value: i64) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Ignores everything.
+impl Hook for () {}
+
+/// Hooks are notified in order, stopping at the first error.
+impl Hook for Vec<&mut dyn Hook> {
+    fn on_statement(&mut self, frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        self.iter_mut()
+            .try_for_each(|v| v.on_statement(frames, stmt))
+    }
+
+    fn on_condition(
+        &mut self,
+        frames: &[Frame],
+        stmt: &Statement,
+        value: i64,
+    ) -> Result<(), Error> {
+        self.iter_mut()
+            .try_for_each(|v| v.on_condition(frames, stmt, value))
+    }
+
+    fn on_assign(
+        &mut self,
+        frames: &[Frame],
+        var: &str,
+        old: Option<i64>,
+        new: i64,
+    ) -> Result<(), Error> {
+        self.iter_mut()
+            .try_for_each(|v| v.on_assign(frames, var, old, new))
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        self.iter_mut().try_for_each(|v| v.on_call(frames))
+    }
+
+    fn on_return(&mut self, frames: &[Frame], value: i64) -> Result<(), Error> {
+        self.iter_mut().try_for_each(|v| v.on_return(frames, value))
+    }
+}
+
+/// State shared by all function instances throughout one execution.
+struct Context<'a> {
+    io: &'a mut dyn Io,
+    hook: Option<&'a mut dyn Hook>,
+    // whitespace-separated input tokens not yet consumed by `scan`
+    pending: VecDeque<String>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Context<'a> {
+    fn new(io: &'a mut dyn Io, hook: Option<&'a mut dyn Hook>) -> Self {
+        Self {
+            io,
+            hook,
+            pending: VecDeque::new(),
+            frames: vec![],
+        }
+    }
+
+    fn notify<F>(&mut self, event: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut dyn Hook, &[Frame]) -> Result<(), Error>,
+    {
+        match &mut self.hook {
+            Some(hook) => event(&mut **hook, &self.frames),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads lines until an input token is buffered, optionally prompting
+    /// before each. Returns false once input is exhausted.
+    fn fill(&mut self, prompt: bool) -> bool {
+        while self.pending.is_empty() {
+            if prompt {
+                self.io.write_out("  > ");
+            }
+            match self.io.read_line() {
+                Some(line) => self
+                    .pending
+                    .extend(line.split_whitespace().map(String::from)),
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+struct RunInstance<'a, 'b> {
+    prog: &'a Program,
+    ctx: &'a mut Context<'b>,
+}
+
+impl RunInstance<'_, '_> {
+    fn frame(&mut self) -> &mut Frame {
+        self.ctx.frames.last_mut().expect("no active frame")
+    }
+
+    fn scope(&mut self) -> &mut HashMap<Token, Variable> {
+        &mut self.frame().scope
+    }
+}
+
+/// A built-in operator.
+struct Builtin {
+    name: &'static str,
+    arity: usize,
+    /// Symbol standing for the operator in infix notation, along with its
+    /// binding strength there, tighter for higher values.
+    infix: Option<(&'static str, u8)>,
+}
+
+const fn builtin(name: &'static str, arity: usize, infix: Option<(&'static str, u8)>) -> Builtin {
+    Builtin { name, arity, infix }
+}
+
+/// All built-in operators, following C for their infix precedence.
+const BUILTINS: &[Builtin] = &[
+    builtin("scan", 0, None),
+    builtin("eof", 0, None),
+    builtin("or", 2, Some(("||", 1))),
+    builtin("and", 2, Some(("&&", 2))),
+    builtin("xor", 2, Some(("^", 3))),
+    builtin("==", 2, Some(("==", 4))),
+    builtin("!=", 2, Some(("!=", 4))),
+    builtin("<", 2, Some(("<", 5))),
+    builtin(">", 2, Some((">", 5))),
+    builtin("<=", 2, Some(("<=", 5))),
+    builtin(">=", 2, Some((">=", 5))),
+    builtin("+", 2, Some(("+", 6))),
+    builtin("-", 2, Some(("-", 6))),
+    builtin("*", 2, Some(("*", 7))),
+    builtin("/", 2, Some(("/", 7))),
+    builtin("%", 2, Some(("%", 7))),
+    builtin("not", 1, Some(("!", 8))),
+];
+
+fn find_builtin(op: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|v| v.name == op)
+}
+
+/// Parameter count of built-in operators, or `None` for anything else.
+fn builtin_arity(op: &str) -> Option<usize> {
+    find_builtin(op).map(|v| v.arity)
+}
+
+fn eval_expr_func(
+    instance: &mut RunInstance,
+    expr: &Expr,
+    ptr: &mut usize,
+    line: usize,
+) -> Result<Variable, Error> {
+    // detect out-of-bounds error
+    if *ptr >= expr.tokens.len() {
+        return Err(Error::BadExpression { line });
+    }
+    // retrieve function parameter count
+    let op = &expr.tokens[*ptr];
+    let op_token: &str = &op.value;
+    let op_cnt = match find_builtin(op_token) {
+        Some(builtin) => builtin.arity,
+        None => {
+            // parse constant first
+            if let Ok(v) = op_token.parse() {
+                return Ok(Variable::from(v));
+            }
+            if let Some(v) = instance.scope().get(op) {
+                // variable takes precedence
+                return Ok(*v);
+            } else if let Some(cnt) = instance.prog.arity(op) {
+                // then attempt to call function
+                cnt
+            } else {
+                // and nothing else
+                return Err(Error::UndeclaredToken {
+                    line,
+                    value: String::from(op_token),
+                });
+            }
+        }
+    };
+    // parse parameters
+    let mut params = vec![];
+    for _ in 0..op_cnt {
+        *ptr += 1;
+        params.push(eval_expr_func(instance, expr, ptr, line)?);
+    }
+    // evaluate result
+    let v = &params;
+    let is = |i: usize| -> bool { v[i].data != 0 };
+    Ok(match op_token {
+        "scan" => {
+            if !instance.ctx.fill(!instance.prog.raw) {
+                return Err(Error::EndOfInput { line });
+            }
+            let inp = instance.ctx.pending.pop_front().unwrap_or_default();
+            match inp.parse() {
+                Ok(v) => Variable::from(v),
+                Err(_) => return Err(Error::InputError { line, value: inp }),
+            }
+        }
+        "eof" => Variable::from(if instance.ctx.fill(!instance.prog.raw) {
+            0
+        } else {
+            1
+        }),
+        "+" => v[0] + v[1],
+        "-" => v[0] - v[1],
+        "*" => v[0] * v[1],
+        "%" => v[0] % v[1],
+        "/" => v[0] / v[1],
+        "==" => Variable::from(if v[0] == v[1] { 1 } else { 0 }),
+        "<" => Variable::from(if v[0] < v[1] { 1 } else { 0 }),
+        ">" => Variable::from(if v[0] > v[1] { 1 } else { 0 }),
+        "<=" => Variable::from(if v[0] <= v[1] { 1 } else { 0 }),
+        ">=" => Variable::from(if v[0] >= v[1] { 1 } else { 0 }),
+        "!=" => Variable::from(if v[0] != v[1] { 1 } else { 0 }),
+        "and" => Variable::from(if is(0) && is(1) { 1 } else { 0 }),
+        "or" => Variable::from(if is(0) || is(1) { 1 } else { 0 }),
+        "xor" => Variable::from(if is(0) != is(1) { 1 } else { 0 }),
+        "not" => Variable::from(if is(0) { 0 } else { 1 }),
+        _ => call_function(instance.prog, instance.ctx, op, params, line)?,
+    })
+}
+
+fn eval_expr(instance: &mut RunInstance, expr: &Expr, from_line: usize) -> Result<Variable, Error> {
+    let mut ptr = 0;
+    let res = eval_expr_func(instance, expr, &mut ptr, from_line)?;
+    if ptr + 1 < expr.tokens.len() {
+        return Err(Error::BadExpression { line: from_line });
+    }
+    Ok(res)
+}
+
+fn format_print(instance: &mut RunInstance, expr: &Expr, line: usize) -> Result<String, Error> {
+    // collect values, each expression delimited by operator arity
+    let mut vals = vec![];
+    let mut ptr = 0;
+    while ptr < expr.tokens.len() {
+        // standard dialect prints nothing but variables
+        let token = &expr.tokens[ptr];
+        if instance.prog.dialect == Dialect::Standard && !instance.scope().contains_key(token) {
+            return Err(Error::UndeclaredToken {
+                line,
+                value: String::from(&token.value),
+            });
+        }
+        let val = eval_expr_func(instance, expr, &mut ptr, line)?;
+        vals.push(format!("{}", val.data));
+        ptr += 1;
+    }
+    match instance.prog.raw {
+        true => Ok(format!("{}\n", vals.join(" "))),
+        false => Ok(format!(
+            "   {}\n",
+            vals.iter().map(|v| format!(" {}", v)).collect::<String>()
+        )),
+    }
+}
+
+fn exec_statement(instance: &mut RunInstance, stmt: &Statement) -> Result<(), Error> {
+    instance.frame().line = stmt.line();
+    instance
+        .ctx
+        .notify(|hook, frames| hook.on_statement(frames, stmt))?;
+    match stmt {
+        Statement::Assign { var, expr, line } => {
+            if lexer::is_keyword(&var.value) || instance.prog.contains(var) {
+                return Err(Error::DuplicateToken {
+                    line: *line,
+                    value: String::from(&var.value),
+                });
+            }
+            let res = eval_expr(instance, expr, *line)?;
+            let old = instance.scope().insert(var.clone(), res);
+            let (old, new) = (old.map(|v| v.clamped()), res.clamped());
+            instance
+                .ctx
+                .notify(|hook, frames| hook.on_assign(frames, &var.value, old, new))?;
+        }
+        Statement::Cond { expr, child, line } => {
+            let cond = eval_expr(instance, expr, *line)?;
+            let value = cond.clamped();
+            instance
+                .ctx
+                .notify(|hook, frames| hook.on_condition(frames, stmt, value))?;
+            if cond.data != 0 {
+                exec_node(instance, child)?;
+            }
+        }
+        Statement::Loop { expr, child, line } => loop {
+            let cond = eval_expr(instance, expr, *line)?;
+            // nested statements may have moved the frame's line along
+            instance.frame().line = *line;
+            let value = cond.clamped();
+            instance
+                .ctx
+                .notify(|hook, frames| hook.on_condition(frames, stmt, value))?;
+            if cond.data == 0 {
+                break;
+            }
+            exec_node(instance, child)?;
+        },
+        Statement::Print { expr, line } => {
+            // flush into stdout in one go
+            let out = format_print(instance, expr, *line)?;
+            instance.ctx.io.write_out(&out);
+        }
+        Statement::Eprint { expr, line } => {
+            let out = format_print(instance, expr, *line)?;
+            instance.ctx.io.write_err(&out);
+        }
+        Statement::Assert { expr, line } => {
+            let cond = eval_expr(instance, expr, *line)?;
+            if cond.data == 0 {
+                // report each referenced variable once, in order of appearance
+                let mut vars: Vec<String> = vec![];
+                for token in &expr.tokens {
+                    if let Some(v) = instance.scope().get(token) {
+                        let var = format!("{} = {}", token.value, v.data);
+                        if !vars.contains(&var) {
+                            vars.push(var);
+                        }
+                    }
+                }
+                let source: Vec<_> = expr.tokens.iter().map(|t| t.value.as_str()).collect();
+                let mut value = format!("{:?} failed", source.join(" "));
+                if !vars.is_empty() {
+                    value = format!("{}, with {}", value, vars.join(", "));
+                }
+                return Err(Error::AssertionFailed { line: *line, value });
+            }
+        }
+        Statement::Ret { line, .. } => return Err(Error::MisplacedRet { line: *line }),
+        Statement::Func { line, .. } => return Err(Error::WildFunction { line: *line }),
+        Statement::Test { line, .. } => return Err(Error::WildTest { line: *line }),
+    }
+    Ok(())
+}
+
+fn exec_node(instance: &mut RunInstance, node: &Node) -> Result<(), Error> {
+    for stmt in &node.stmts {
+        exec_statement(instance, stmt)?;
+    }
+    Ok(())
+}
+
+fn call_native(
+    native: &Native,
+    params: Vec<Variable>,
+    from_line: usize,
+) -> Result<Variable, Error> {
+    let args = params
+        .iter()
+        .map(|v| v.to_host(from_line))
+        .collect::<Result<Vec<_>, _>>()?;
+    match (native.func)(&args) {
+        Ok(v) => Ok(Variable::from(v as i128)),
+        Err(value) => Err(Error::NativeError {
+            line: from_line,
+            value,
+        }),
+    }
+}
+
+fn call_function(
+    prog: &Program,
+    ctx: &mut Context,
+    token: &Token,
+    params: Vec<Variable>,
+    from_line: usize,
+) -> Result<Variable, Error> {
+    // lookup function
+    if let Some(native) = prog.natives.get(token) {
+        return call_native(native, params, from_line);
+    }
+    let func = match prog.funcs.get(token) {
+        Some(func) => func,
+        None => {
+            return Err(Error::UndeclaredToken {
+                line: from_line,
+                value: String::from(&token.value),
+            })
+        }
+    };
+    // put parameters into scope
+    let mut scope = HashMap::new();
+    for (key, value) in func.params.iter().zip(params) {
+        if prog.contains(key) {
+            return Err(Error::DuplicateToken {
+                line: func.line,
+                value: String::from(&key.value),
+            });
+        }
+        scope.insert(key.clone(), value);
+    }
+    // generate instance on a fresh frame
+    let name = String::from(&token.value);
+    let line = func.line;
+    ctx.frames.push(Frame { name, line, scope });
+    let mut instance = RunInstance { prog, ctx };
+    let res = exec_function(&mut instance, func, from_line);
+    ctx.frames.pop();
+    res
+}
+
+fn exec_function(
+    instance: &mut RunInstance,
+    func: &Function,
+    from_line: usize,
+) -> Result<Variable, Error> {
+    instance.ctx.notify(|hook, frames| hook.on_call(frames))?;
+    // iterate function statements
+    let stmts = &func.root.stmts;
+    let last = match stmts.last() {
+        Some(stmt) => stmt,
+        None => return Err(Error::MisplacedRet { line: func.line }),
+    };
+    for stmt in &stmts[..stmts.len() - 1] {
+        exec_statement(instance, stmt)?;
+    }
+    // last statement must return value
+    let expr = match last {
+        Statement::Ret { expr, .. } => expr,
+        _ => return Err(Error::MisplacedRet { line: func.line }),
+    };
+    instance.frame().line = last.line();
+    instance
+        .ctx
+        .notify(|hook, frames| hook.on_statement(frames, last))?;
+    let res = eval_expr(instance, expr, from_line)?;
+    let value = res.clamped();
+    instance
+        .ctx
+        .notify(|hook, frames| hook.on_return(frames, value))?;
+    Ok(res)
+}
+
+pub fn format_runtime_err(
+    filename: Option<&str>,
+    lines: &[String],
+    err: &Error,
+    line_offset: usize,
+) -> String {
+    let line = err.line();
+    let header = match filename {
+        Some(v) => format!("{}:{}: error: ", v, line + line_offset),
+        None => format!("stdin:{}: error: ", line + line_offset),
+    };
+    let padding: String = (2..header.len()).map(|_| ' ').collect();
+    let line = if let Some(v) = lines.get(line) { v } else { "" };
+    format!("{}{}\n{}> {}\n", header, err, padding, line.trim())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Interactive sessions
+
+/// Incremental execution state behind the interactive console. Lines are fed
+/// one at a time; functions are defined as they close and other statements
+/// run as an ever-growing `main` body sharing a single scope.
+pub struct Session {
+    // first empty line is magic, used to avoid -1 pointers
+    // output debug messages need to be checked for sanity (line + 1)
+    lines: Vec<String>,
+    ptr: usize,
+    prog: Program,
+    main_stmts: Vec<Statement>,
+    scope: HashMap<Token, Variable>,
+    // the next statement to execute main_stmts[..]
+    exec_ptr: usize,
+    // the last validated lines[..]
+    last_ptr: usize,
+    // input tokens left over by `scan`
+    pending: VecDeque<String>,
+}
+
+impl Session {
+    pub fn new(prog: Program) -> Self {
+        Self {
+            lines: vec![String::default()],
+            ptr: 0,
+            prog,
+            main_stmts: vec![],
+            scope: HashMap::new(),
+            exec_ptr: 0,
+            last_ptr: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Appends a line and executes every statement completed by it. Returns
+    /// `Error::UnclosedBlock` while a block is still awaiting its `end`.
+    pub fn feed(&mut self, line: String) -> Result<(), Error> {
+        while self.ptr > 0 && self.ptr > self.lines.len() {
+            self.ptr -= 1;
+        }
+        self.lines.push(line);
+        self.execute_block()
+    }
+
+    /// Rewinds the parser to the last complete statement so that the next
+    /// fed line continues the pending block.
+    pub fn rewind(&mut self) {
+        self.ptr = self.last_ptr;
+    }
+
+    /// Discards the lines and statements fed since the last success.
+    pub fn rollback(&mut self) {
+        self.lines.truncate(self.last_ptr + 1);
+        self.main_stmts.truncate(self.exec_ptr);
+        self.ptr = self.last_ptr;
+    }
+
+    fn execute_block(&mut self) -> Result<(), Error> {
+        // try to parse node into statements
+        let mut state = State {
+            lines: &self.lines,
+            ptr: self.ptr,
+            dialect: self.prog.dialect,
+            lossless: false,
+        };
+        let node = parse_node(&mut state, "");
+        self.ptr = state.ptr;
+        // validate all statements, adding function, denying return
+        for stmt in node?.stmts {
+            if let Statement::Func { .. } | Statement::Test { .. } = stmt {
+                self.prog.define(stmt)?;
+            } else if let Statement::Ret { line, .. } = stmt {
+                return Err(Error::WildStatement { line });
+            } else {
+                self.main_stmts.push(stmt);
+            }
+        }
+        // create instance
+        let mut io = StdIo;
+        let mut ctx = Context::new(&mut io, None);
+        ctx.pending = std::mem::take(&mut self.pending);
+        ctx.frames.push(Frame {
+            name: String::from("main"),
+            line: 0,
+            scope: self.scope.clone(),
+        });
+        let mut instance = RunInstance {
+            prog: &self.prog,
+            ctx: &mut ctx,
+        };
+        // attempt execution
+        let mut new_exec_ptr = self.exec_ptr;
+        let mut res = Ok(());
+        while res.is_ok() && new_exec_ptr < self.main_stmts.len() {
+            let stmt = &self.main_stmts[new_exec_ptr];
+            res = exec_statement(&mut instance, stmt);
+            new_exec_ptr += 1;
+        }
+        // input consumed by `scan` is gone, even if a statement failed
+        self.pending = std::mem::take(&mut ctx.pending);
+        res?;
+        // writeback state
+        if let Some(frame) = ctx.frames.pop() {
+            self.scope = frame.scope;
+        }
+        self.last_ptr = self.ptr - 1;
+        self.exec_ptr = new_exec_ptr;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "# about f
+function f x as # header
+    # first
+    let y = + x 1
+
+    # before end
+end function # closing
+
+# about main
+function main as
+    return f 1
+end function
+# end of file
+";
+
+    fn child(stmt: &Statement) -> &Node {
+        match stmt {
+            Statement::Func { child, .. } => child,
+            _ => panic!("expected a function, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn lossless_attaches_comments_to_functions() {
+        let root = parse_lossless(SOURCE, Dialect::Standard).unwrap();
+        assert_eq!(root.stmts.len(), 2);
+        assert_eq!(root.comments[0], vec!["# about f"]);
+        assert_eq!(root.comments[1], vec!["", "# about main"]);
+        assert_eq!(root.remarks[0].as_deref(), Some("# header"));
+        assert_eq!(root.remarks[1], None);
+    }
+
+    #[test]
+    fn lossless_attaches_comments_to_statements() {
+        let root = parse_lossless(SOURCE, Dialect::Standard).unwrap();
+        let f = child(&root.stmts[0]);
+        assert_eq!(f.comments, vec![vec!["# first"]]);
+        assert_eq!(f.remarks, vec![None]);
+        assert_eq!(child(&root.stmts[1]).comments, vec![Vec::<String>::new()]);
+    }
+
+    #[test]
+    fn lossless_keeps_comments_before_end() {
+        let root = parse_lossless(SOURCE, Dialect::Standard).unwrap();
+        let f = child(&root.stmts[0]);
+        assert_eq!(f.trailing, vec!["", "# before end"]);
+        assert_eq!(f.end_remark.as_deref(), Some("# closing"));
+    }
+
+    #[test]
+    fn lossless_keeps_comments_at_end_of_file() {
+        let root = parse_lossless(SOURCE, Dialect::Standard).unwrap();
+        assert_eq!(root.trailing, vec!["# end of file"]);
+        let root = parse_lossless("# only a comment\n\n", Dialect::Standard).unwrap();
+        assert!(root.stmts.is_empty());
+        assert_eq!(root.trailing, vec!["# only a comment", ""]);
+    }
+
+    #[test]
+    fn parse_discards_comments() {
+        let root = parse(SOURCE, Dialect::Standard).unwrap();
+        assert_eq!(root.stmts.len(), 2);
+        assert!(root.comments.is_empty());
+        assert!(root.remarks.is_empty());
+        assert!(root.trailing.is_empty());
+        assert!(child(&root.stmts[0]).end_remark.is_none());
+    }
+
+    fn load(source: &str) -> Program {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.load(source).unwrap();
+        prog
+    }
+
+    /// Evaluates to -(2^47 - 1)^2, far below `i64::MIN`.
+    const HUGE: &str = "function main as
+    let a = * -140737488355327 140737488355327
+    let b = big a
+    return b
+end function
+";
+
+    #[test]
+    fn natives_are_called_like_functions() {
+        let mut prog = Program::new();
+        prog.register_native("big", 1, |args| Ok(args[0] * 2))
+            .unwrap();
+        prog.load("function main as\n    return big 21\nend function\n")
+            .unwrap();
+        assert_eq!(prog.call("main", vec![]), Ok(42));
+        assert_eq!(prog.call("big", vec![4]), Ok(8));
+    }
+
+    #[test]
+    fn natives_report_their_errors() {
+        let mut prog = Program::new();
+        prog.register_native("big", 1, |_| Err(String::from("no")))
+            .unwrap();
+        prog.load(
+            HUGE.replace("* -140737488355327 140737488355327", "1")
+                .as_str(),
+        )
+        .unwrap();
+        assert_eq!(
+            prog.call("main", vec![]),
+            Err(Error::NativeError {
+                line: 2,
+                value: String::from("no")
+            })
+        );
+    }
+
+    #[test]
+    fn natives_may_not_clash_with_other_names() {
+        let duplicate = |value: &str, line| Error::DuplicateToken {
+            line,
+            value: String::from(value),
+        };
+        let mut prog = Program::new();
+        prog.register_native("big", 1, |args| Ok(args[0])).unwrap();
+        assert_eq!(
+            prog.register_native("big", 2, |args| Ok(args[0])),
+            Err(duplicate("big", 0))
+        );
+        assert_eq!(
+            prog.register_native("print", 0, |_| Ok(0)),
+            Err(duplicate("print", 0))
+        );
+        assert_eq!(
+            prog.load("function big x as\n    return x\nend function\n"),
+            Err(duplicate("big", 0))
+        );
+        prog.load("function main as\n    let big = 1\n    return big\nend function\n")
+            .unwrap();
+        assert_eq!(prog.call("main", vec![]), Err(duplicate("big", 1)));
+    }
+
+    #[test]
+    fn values_beyond_64_bits_are_not_truncated() {
+        let out_of_range = |line| Error::OutOfRange {
+            line,
+            value: String::from("-19807040628565802923409276929"),
+        };
+        let mut prog = Program::new();
+        prog.register_native("big", 1, |args| Ok(args[0])).unwrap();
+        prog.load(HUGE).unwrap();
+        assert_eq!(prog.call("main", vec![]), Err(out_of_range(2)));
+        let prog = load(&HUGE.replace("big a", "a"));
+        assert_eq!(prog.call("main", vec![]), Err(out_of_range(0)));
+    }
+
+    #[test]
+    fn hooks_see_values_clamped() {
+        #[derive(Default)]
+        struct Recorder(Vec<i64>);
+        impl Hook for Recorder {
+            fn on_assign(
+                &mut self,
+                frames: &[Frame],
+                var: &str,
+                _old: Option<i64>,
+                new: i64,
+            ) -> Result<(), Error> {
+                self.0.push(new);
+                self.0.push(frames[0].get(var).unwrap_or_default());
+                Ok(())
+            }
+        }
+        let prog = load(&HUGE.replace("big a", "a").replace("return b", "return 0"));
+        let mut recorder = Recorder::default();
+        let mut io = BufferIo::new("");
+        assert_eq!(prog.execute_hooked(&mut io, &mut recorder), Ok(0));
+        assert_eq!(recorder.0, [i64::MIN; 4]);
+    }
+
+    const FASTPOW: &str = include_str!("../samples/fastpow.nh");
+
+    #[test]
+    fn calls_functions_with_arguments() {
+        let prog = load(FASTPOW);
+        assert_eq!(prog.call("fastpow", vec![2, 10]), Ok(1024));
+        assert_eq!(prog.call("fastpow", vec![-3, 3]), Ok(-27));
+        assert_eq!(
+            prog.call("fastpow", vec![2]),
+            Err(Error::BadArguments {
+                line: 0,
+                value: String::from("fastpow")
+            })
+        );
+        assert_eq!(
+            prog.call("slowpow", vec![2, 10]),
+            Err(Error::UndeclaredToken {
+                line: 0,
+                value: String::from("slowpow")
+            })
+        );
+    }
+
+    #[test]
+    fn failed_assertions_show_variable_values() {
+        let prog = load(
+            "function main as\n    let x = 3\n    let y = 4\n    assert > x + y x\n    return 0\nend function\n",
+        );
+        assert_eq!(
+            prog.execute_with(&mut BufferIo::new("")),
+            Err(Error::AssertionFailed {
+                line: 3,
+                value: String::from("\"> x + y x\" failed, with x = 3, y = 4")
+            })
+        );
+        let prog =
+            load("function main as\n    assert 1\n    assert 0\n    return 0\nend function\n");
+        let err = prog.execute_with(&mut BufferIo::new("")).unwrap_err();
+        assert_eq!(err.format(), "assertion \"0\" failed");
+        assert_eq!(err.line(), 2);
+    }
+
+    const PRINTS: &str =
+        "function main as\n    let x = 6\n    print x * x x 7 -1\n    return 0\nend function\n";
+
+    #[test]
+    fn extended_dialect_prints_expressions() {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.set_dialect(Dialect::Extended);
+        prog.load(PRINTS).unwrap();
+        let mut io = BufferIo::new("");
+        assert_eq!(prog.execute_with(&mut io), Ok(0));
+        assert_eq!(io.stdout, "6 36 7 -1\n");
+        let mut prog = Program::new();
+        assert_eq!(
+            prog.load(PRINTS),
+            Err(Error::IllegalChar {
+                line: 2,
+                value: '*'
+            })
+        );
+    }
+
+    #[test]
+    fn eprint_writes_to_the_error_stream() {
+        let prog = load(
+            "function main as\n    let x = 1\n    let y = 2\n    print x\n    eprint x y\n    eprint\n    return 0\nend function\n",
+        );
+        let mut io = BufferIo::new("");
+        assert_eq!(prog.execute_with(&mut io), Ok(0));
+        assert_eq!(io.stdout, "1\n");
+        assert_eq!(io.stderr, "1 2\n\n");
+    }
+
+    const ECHO: &str = "function main as\n    let a = scan\n    let b = scan\n    print a b\n    return 0\nend function\n";
+
+    #[test]
+    fn raw_mode_omits_prefix_and_prompt() {
+        let mut prog = Program::new();
+        prog.load(ECHO).unwrap();
+        let mut io = BufferIo::new("1\n2\n");
+        prog.execute_with(&mut io).unwrap();
+        assert_eq!(io.stdout, "  >   >     1 2\n");
+        prog.set_raw(true);
+        let mut io = BufferIo::new("1\n2\n");
+        prog.execute_with(&mut io).unwrap();
+        assert_eq!(io.stdout, "1 2\n");
+    }
+
+    #[test]
+    fn scan_reads_tokens_across_lines() {
+        let prog = load(ECHO);
+        let mut io = BufferIo::new("  1\t 2 3\n");
+        assert_eq!(prog.execute_with(&mut io), Ok(0));
+        assert_eq!(io.stdout, "1 2\n");
+        let mut io = BufferIo::new("\n-4\n\n5\n");
+        assert_eq!(prog.execute_with(&mut io), Ok(0));
+        assert_eq!(io.stdout, "-4 5\n");
+        let mut io = BufferIo::new("1\n  \n");
+        assert_eq!(
+            prog.execute_with(&mut io),
+            Err(Error::EndOfInput { line: 2 })
+        );
+        let mut io = BufferIo::new("1 x");
+        assert_eq!(
+            prog.execute_with(&mut io),
+            Err(Error::InputError {
+                line: 2,
+                value: String::from("x")
+            })
+        );
+    }
+
+    #[test]
+    fn sessions_keep_tokens_left_after_errors() {
+        let mut session = Session::new(Program::new());
+        session
+            .pending
+            .extend(["1", "2", "3"].iter().map(|v| String::from(*v)));
+        assert_eq!(
+            session.feed(String::from("let a = + scan b")),
+            Err(Error::UndeclaredToken {
+                line: 1,
+                value: String::from("b")
+            })
+        );
+        session.rollback();
+        assert_eq!(session.pending, ["2", "3"]);
+        session.feed(String::from("let a = scan")).unwrap();
+        assert_eq!(session.pending, ["3"]);
+        assert_eq!(session.scope[&Token::from_name(0, "a").unwrap()].data, 2);
+    }
+
+    #[test]
+    fn eof_detects_the_end_of_input() {
+        let prog = load(
+            "function main as\n    let sum = 0\n    while not eof do\n        let sum = + sum scan\n    end while\n    print sum\n    return eof\nend function\n",
+        );
+        let mut io = BufferIo::new("1 2\n3\n\n  \n");
+        assert_eq!(prog.execute_with(&mut io), Ok(1));
+        assert_eq!(io.stdout, "6\n");
+        let mut io = BufferIo::new("");
+        assert_eq!(prog.execute_with(&mut io), Ok(1));
+        assert_eq!(io.stdout, "0\n");
+    }
+
+    #[test]
+    fn loads_tabs_crlf_and_bom() {
+        let source = "\u{feff}# sums\r\nfunction main as\r\n\tlet a = 1\t# one\r\n\tlet b =\t+ a  a\r\n\tprint a b\r\n\treturn b\r\nend function\r\n";
+        let prog = load(source);
+        let mut io = BufferIo::new("");
+        assert_eq!(prog.execute_with(&mut io), Ok(2));
+        assert_eq!(io.stdout, "1 2\n");
+        let mut prog = Program::new();
+        assert_eq!(
+            prog.load("function main as\r\n\tlet a = ?\r\n\treturn a\r\nend function\r\n"),
+            Err(Error::IllegalChar {
+                line: 1,
+                value: '?'
+            })
+        );
+    }
+
+    #[test]
+    fn assignments_need_an_equals_sign() {
+        let mut prog = Program::new();
+        assert_eq!(
+            prog.load("function main as\n    let x foo 5\n    return x\nend function\n"),
+            Err(Error::MalformedAssign { line: 1 })
+        );
+        assert_eq!(
+            parse(
+                "function main as\n    let x 5\n    return 0\nend function\n",
+                Dialect::Standard
+            )
+            .unwrap_err(),
+            Error::MalformedAssign { line: 1 }
+        );
+    }
+}
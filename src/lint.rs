@@ -0,0 +1,455 @@
+//! Static checks pointing out code that is legal but most likely wrong.
+//!
+//! The linter walks the functions of a loaded `Program`. It never runs any
+//! code, so it cannot know about values; every check errs on the side of
+//! staying silent.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{builtin_arity, Expr, Function, Node, Program, Statement, Token};
+
+/// The individual checks, each of which may be suppressed on its own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    DeadAssignment,
+    ConstantLoop,
+    UncalledFunction,
+    ConstantCondition,
+}
+
+pub const LINTS: &[Lint] = &[
+    Lint::UnusedVariable,
+    Lint::UnusedParameter,
+    Lint::DeadAssignment,
+    Lint::ConstantLoop,
+    Lint::UncalledFunction,
+    Lint::ConstantCondition,
+];
+
+impl Lint {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedVariable => "W01",
+            Self::UnusedParameter => "W02",
+            Self::DeadAssignment => "W03",
+            Self::ConstantLoop => "W04",
+            Self::UncalledFunction => "W05",
+            Self::ConstantCondition => "W06",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UnusedVariable => "unused-variable",
+            Self::UnusedParameter => "unused-parameter",
+            Self::DeadAssignment => "dead-assignment",
+            Self::ConstantLoop => "constant-loop",
+            Self::UncalledFunction => "uncalled-function",
+            Self::ConstantCondition => "constant-condition",
+        }
+    }
+
+    /// Looks up a lint by either its code or its name.
+    pub fn find(s: &str) -> Option<Self> {
+        LINTS
+            .iter()
+            .copied()
+            .find(|l| l.code() == s || l.name() == s)
+    }
+}
+
+#[derive(Debug)]
+pub struct Warning {
+    pub lint: Lint,
+    pub line: usize,
+    pub message: String,
+}
+
+/// Runs all lints not in `allowed` over a program, ordered by line.
+pub fn lint(prog: &Program, allowed: &[Lint]) -> Vec<Warning> {
+    let mut res = vec![];
+    for (name, func) in prog
+        .funcs
+        .iter()
+        .chain(prog.tests.iter().map(|(k, v)| (k, v)))
+    {
+        lint_function(prog, name, func, &mut res);
+    }
+    lint_calls(prog, &mut res);
+    res.retain(|w| !allowed.contains(&w.lint));
+    res.sort_by_key(|w| (w.line, w.lint.code(), w.message.clone()));
+    res
+}
+
+/// Renders a warning the same way runtime errors are shown.
+pub fn format_warning(
+    filename: Option<&str>,
+    lines: &[String],
+    warning: &Warning,
+    line_offset: usize,
+) -> String {
+    let header = format!(
+        "{}:{}: warning[{}]: ",
+        filename.unwrap_or("stdin"),
+        warning.line + line_offset,
+        warning.lint.code()
+    );
+    let padding: String = (2..header.len()).map(|_| ' ').collect();
+    let line = lines.get(warning.line).map_or("", |v| v.as_str());
+    format!(
+        "{}{} [{}]\n{}> {}\n",
+        header,
+        warning.message,
+        warning.lint.name(),
+        padding,
+        line.trim()
+    )
+}
+
+fn lint_function(prog: &Program, name: &Token, func: &Function, res: &mut Vec<Warning>) {
+    // every name assigned to is a local variable, as are parameters
+    let mut assigned = HashMap::new();
+    collect_assigned(&func.root, &mut assigned);
+    let mut locals: HashSet<&str> = assigned.keys().copied().collect();
+    locals.extend(func.params.iter().map(|p| p.value.as_str()));
+    let mut reads = HashSet::new();
+    collect_reads(&func.root, &mut reads);
+    // variables and parameters never read
+    for (var, line) in &assigned {
+        if !reads.contains(var) && !func.params.iter().any(|p| p.value == *var) {
+            res.push(Warning {
+                lint: Lint::UnusedVariable,
+                line: *line,
+                message: format!("variable {:?} is never used", var),
+            });
+        }
+    }
+    for param in &func.params {
+        if !reads.contains(param.value.as_str()) {
+            res.push(Warning {
+                lint: Lint::UnusedParameter,
+                line: func.line,
+                message: format!(
+                    "parameter {:?} of {:?} is never read",
+                    param.value, name.value
+                ),
+            });
+        }
+    }
+    lint_node(prog, &func.root, &locals, &reads, res);
+}
+
+fn lint_node(
+    prog: &Program,
+    node: &Node,
+    locals: &HashSet<&str>,
+    reads: &HashSet<&str>,
+    res: &mut Vec<Warning>,
+) {
+    for (i, stmt) in node.stmts.iter().enumerate() {
+        match stmt {
+            // unread variables are reported as unused already
+            Statement::Assign { var, line, .. }
+                if reads.contains(var.value.as_str())
+                    && is_overwritten(&var.value, &node.stmts[i + 1..]) =>
+            {
+                res.push(Warning {
+                    lint: Lint::DeadAssignment,
+                    line: *line,
+                    message: format!("value assigned to {:?} is never read", var.value),
+                });
+            }
+            Statement::Cond { expr, child, line } => {
+                if is_constant(expr) {
+                    res.push(Warning {
+                        lint: Lint::ConstantCondition,
+                        line: *line,
+                        message: String::from("condition is always the same"),
+                    });
+                }
+                lint_node(prog, child, locals, reads, res);
+            }
+            Statement::Loop { expr, child, line } => {
+                if is_loop_invariant(prog, expr, child, locals) {
+                    res.push(Warning {
+                        lint: Lint::ConstantLoop,
+                        line: *line,
+                        message: String::from("loop condition is never changed by the loop body"),
+                    });
+                }
+                lint_node(prog, child, locals, reads, res);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn lint_calls(prog: &Program, res: &mut Vec<Warning>) {
+    // walk the call graph starting from main, tests being callers as well
+    let main = Token {
+        value: String::from("main"),
+    };
+    if !prog.funcs.contains_key(&main) {
+        return;
+    }
+    let mut visited = HashSet::new();
+    let mut queue = vec![&main];
+    let mut exprs = vec![];
+    for (_, test) in &prog.tests {
+        collect_exprs(&test.root, &mut exprs);
+    }
+    for token in exprs.iter().flat_map(|e| &e.tokens) {
+        if let Some((callee, _)) = prog.funcs.get_key_value(token) {
+            queue.push(callee);
+        }
+    }
+    while let Some(name) = queue.pop() {
+        if !visited.insert(name.value.as_str()) {
+            continue;
+        }
+        let mut exprs = vec![];
+        collect_exprs(&prog.funcs[name].root, &mut exprs);
+        for token in exprs.iter().flat_map(|e| &e.tokens) {
+            if let Some((callee, _)) = prog.funcs.get_key_value(token) {
+                queue.push(callee);
+            }
+        }
+    }
+    for (name, func) in &prog.funcs {
+        if !visited.contains(name.value.as_str()) {
+            res.push(Warning {
+                lint: Lint::UncalledFunction,
+                line: func.line,
+                message: format!(
+                    "function {:?} is never called from main or a test",
+                    name.value
+                ),
+            });
+        }
+    }
+}
+
+/// Whether a variable is assigned again before any of `rest` reads it.
+fn is_overwritten(var: &str, rest: &[Statement]) -> bool {
+    for stmt in rest {
+        let mut reads = HashSet::new();
+        collect_stmt_reads(stmt, &mut reads);
+        if reads.contains(var) {
+            return false;
+        }
+        if let Statement::Assign { var: v, .. } = stmt {
+            if v.value == var {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Whether an expression consists of nothing but constants and operators.
+fn is_constant(expr: &Expr) -> bool {
+    expr.tokens.iter().all(|t| {
+        let v = t.value.as_str();
+        v.parse::<i128>().is_ok() || (builtin_arity(v).is_some() && v != "scan" && v != "eof")
+    })
+}
+
+/// Whether a loop condition only depends on variables its body never
+/// assigns. Conditions reading input or calling functions are left alone.
+fn is_loop_invariant(prog: &Program, expr: &Expr, body: &Node, locals: &HashSet<&str>) -> bool {
+    let mut assigned = HashMap::new();
+    collect_assigned(body, &mut assigned);
+    for token in &expr.tokens {
+        let v = token.value.as_str();
+        let is_input = v == "scan" || v == "eof";
+        let is_changed = locals.contains(v) && assigned.contains_key(v);
+        let is_call = !locals.contains(v) && prog.arity(token).is_some();
+        if is_input || is_changed || is_call {
+            return false;
+        }
+    }
+    true
+}
+
+fn collect_assigned<'a>(node: &'a Node, res: &mut HashMap<&'a str, usize>) {
+    for stmt in &node.stmts {
+        match stmt {
+            Statement::Assign { var, line, .. } => {
+                res.entry(var.value.as_str()).or_insert(*line);
+            }
+            Statement::Cond { child, .. } | Statement::Loop { child, .. } => {
+                collect_assigned(child, res)
+            }
+            _ => (),
+        }
+    }
+}
+
+fn collect_exprs<'a>(node: &'a Node, res: &mut Vec<&'a Expr>) {
+    for stmt in &node.stmts {
+        match stmt {
+            Statement::Assign { expr, .. }
+            | Statement::Print { expr, .. }
+            | Statement::Eprint { expr, .. }
+            | Statement::Ret { expr, .. }
+            | Statement::Assert { expr, .. } => res.push(expr),
+            Statement::Cond { expr, child, .. } | Statement::Loop { expr, child, .. } => {
+                res.push(expr);
+                collect_exprs(child, res);
+            }
+            Statement::Func { .. } | Statement::Test { .. } => (),
+        }
+    }
+}
+
+fn collect_reads<'a>(node: &'a Node, res: &mut HashSet<&'a str>) {
+    for stmt in &node.stmts {
+        collect_stmt_reads(stmt, res);
+    }
+}
+
+fn collect_stmt_reads<'a>(stmt: &'a Statement, res: &mut HashSet<&'a str>) {
+    let mut exprs = vec![];
+    match stmt {
+        Statement::Assign { expr, .. }
+        | Statement::Print { expr, .. }
+        | Statement::Eprint { expr, .. }
+        | Statement::Ret { expr, .. }
+        | Statement::Assert { expr, .. } => exprs.push(expr),
+        Statement::Cond { expr, child, .. } | Statement::Loop { expr, child, .. } => {
+            exprs.push(expr);
+            collect_exprs(child, &mut exprs);
+        }
+        Statement::Func { .. } | Statement::Test { .. } => (),
+    }
+    res.extend(
+        exprs
+            .iter()
+            .flat_map(|e| &e.tokens)
+            .map(|t| t.value.as_str()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exercises every construct the lints look at without triggering any.
+    const CLEAN: &str = "function f x as
+    return + x 1
+end function
+function main as
+    let n = scan
+    while > n 0 do
+        let n = - n 1
+    end while
+    if n then
+        print n
+    end if
+    return f n
+end function
+";
+
+    fn warnings(source: &str, allowed: &[Lint]) -> Vec<(&'static str, usize)> {
+        let mut prog = Program::new();
+        prog.load(source).unwrap();
+        let warnings = lint(&prog, allowed);
+        warnings.iter().map(|w| (w.lint.code(), w.line)).collect()
+    }
+
+    /// The clean program with one line replaced.
+    fn with(line: usize, text: &str) -> String {
+        let mut lines: Vec<_> = CLEAN.lines().collect();
+        lines[line] = text;
+        lines.join("\n")
+    }
+
+    #[test]
+    fn stays_silent_on_clean_code() {
+        assert!(warnings(CLEAN, &[]).is_empty());
+    }
+
+    #[test]
+    fn finds_unused_variables() {
+        let source = with(9, "        let m = n");
+        assert_eq!(warnings(&source, &[]), [("W01", 9)]);
+    }
+
+    #[test]
+    fn finds_unused_parameters() {
+        let source = with(1, "    return 1");
+        assert_eq!(warnings(&source, &[]), [("W02", 0)]);
+    }
+
+    #[test]
+    fn finds_dead_assignments() {
+        let source = with(4, "    let n = 1\n    let n = scan");
+        assert_eq!(warnings(&source, &[]), [("W03", 4)]);
+        // a read in between keeps the first value alive
+        let source = with(4, "    let n = 1\n    print n\n    let n = scan");
+        assert!(warnings(&source, &[]).is_empty());
+    }
+
+    #[test]
+    fn checks_dead_assignments_within_one_block() {
+        let source = "function main as
+    let a = 1
+    if eof then
+        let a = 2
+    end if
+    print a
+    return 0
+end function
+";
+        // the nested block may not run at all
+        assert!(warnings(source, &[]).is_empty());
+        // only later statements of the same block count, so the nested
+        // assignment goes unnoticed
+        let source = source.replace("    print a", "    let a = 3\n    print a");
+        assert_eq!(warnings(&source, &[]), [("W03", 1)]);
+        let source = source.replace("let a = 2", "let a = 2\n        let a = 4");
+        assert_eq!(warnings(&source, &[]), [("W03", 1), ("W03", 3)]);
+    }
+
+    #[test]
+    fn finds_constant_loops() {
+        let source = with(6, "        print n");
+        assert_eq!(warnings(&source, &[]), [("W04", 5)]);
+        // calls may have effects, so they count as changes
+        let source = with(5, "    while > f n 0 do").replace("let n = - n 1", "print n");
+        assert!(warnings(&source, &[]).is_empty());
+    }
+
+    #[test]
+    fn finds_uncalled_functions() {
+        let source = with(11, "    return n");
+        assert_eq!(warnings(&source, &[]), [("W05", 0)]);
+        let source = format!(
+            "{}\ntest calls_f as\n    assert f 1\nend test\n",
+            with(11, "    return n")
+        );
+        assert!(warnings(&source, &[]).is_empty());
+    }
+
+    #[test]
+    fn finds_constant_conditions() {
+        let source = with(8, "    if + 1 2 then");
+        assert_eq!(warnings(&source, &[]), [("W06", 8)]);
+        let source = with(8, "    if eof then");
+        assert!(warnings(&source, &[]).is_empty());
+    }
+
+    #[test]
+    fn suppresses_lints_by_code_or_name() {
+        let source = with(1, "    return 1").replace("    print n", "    let m = n");
+        assert_eq!(warnings(&source, &[]), [("W02", 0), ("W01", 9)]);
+        let allowed = [Lint::find("W01").unwrap()];
+        assert_eq!(warnings(&source, &allowed), [("W02", 0)]);
+        let allowed = [Lint::find("unused-parameter").unwrap()];
+        assert_eq!(warnings(&source, &allowed), [("W01", 9)]);
+        assert_eq!(Lint::find("W07"), None);
+        assert_eq!(Lint::find("unused"), None);
+    }
+}
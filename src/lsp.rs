@@ -0,0 +1,695 @@
+//! Language server speaking the Language Server Protocol over stdio.
+//!
+//! Documents are synchronized in full on every change. Diagnostics come from
+//! loading the document as a `Program` and running the linter over it.
+//! Definitions, hovers and completions use the functions of the loaded
+//! program as well, and fall back to a lexical outline of the code while it
+//! fails to load, so that they keep working while the document is being
+//! edited into shape.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::json::{self, Value};
+use crate::lexer::{self, Kind, Lexeme};
+use crate::lint;
+use crate::{builtin_arity, split_lines, Dialect, Error, Function, Node, Program, Statement};
+
+// LSP constants used by this server
+const SEVERITY_ERROR: i64 = 1;
+const SEVERITY_WARNING: i64 = 2;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_KEYWORD: i64 = 14;
+const COMPLETION_OPERATOR: i64 = 24;
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A function along with the places its names are declared.
+struct Symbol {
+    name: Lexeme,
+    params: Vec<Lexeme>,
+    // first assignment to each variable
+    locals: Vec<Lexeme>,
+    // the line of the closing `end`
+    end: usize,
+}
+
+impl Symbol {
+    fn signature(&self) -> String {
+        let mut res = format!("function {}", self.name.text);
+        for param in &self.params {
+            res.push(' ');
+            res.push_str(&param.text);
+        }
+        res + " as"
+    }
+
+    fn variable(&self, name: &str) -> Option<&Lexeme> {
+        self.params
+            .iter()
+            .chain(&self.locals)
+            .find(|v| v.text == name)
+    }
+}
+
+/// Finds functions by scanning lines, without parsing them.
+fn outline(lines: &[String]) -> Vec<Symbol> {
+    let mut res: Vec<Symbol> = vec![];
+    let mut current = None;
+    let mut depth: usize = 0;
+    for (i, line) in lines.iter().enumerate() {
+        // lines that fail to lex are simply skipped
+        let words: Vec<Lexeme> = match lexer::tokenize_line(line, i) {
+            Ok(v) => v.into_iter().filter(|w| w.kind != Kind::Comment).collect(),
+            Err(_) => continue,
+        };
+        let is_ident = |w: &&Lexeme| w.kind == Kind::Ident;
+        match words.first().map(|w| w.text.as_str()) {
+            Some("function") => {
+                depth = 1;
+                current = None;
+                if let Some(name) = words.get(1).filter(is_ident) {
+                    let params = words[2..]
+                        .iter()
+                        .take_while(|w| w.text != "as")
+                        .filter(is_ident)
+                        .cloned()
+                        .collect();
+                    current = Some(res.len());
+                    res.push(Symbol {
+                        name: name.clone(),
+                        params,
+                        locals: vec![],
+                        end: lines.len() - 1,
+                    });
+                }
+            }
+            // tests have no symbols of their own
+            Some("test") => {
+                depth = 1;
+                current = None;
+            }
+            Some("if") | Some("while") => depth += 1,
+            Some("end") => {
+                depth = depth.saturating_sub(1);
+                if let (0, Some(idx)) = (depth, current) {
+                    res[idx].end = i;
+                    current = None;
+                }
+            }
+            Some("let") => {
+                if let (Some(var), Some(idx)) = (words.get(1).filter(is_ident), current) {
+                    if res[idx].variable(&var.text).is_none() {
+                        res[idx].locals.push(var.clone());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// Locates the names of a loaded function in its source lines.
+fn function_symbol(lines: &[String], func: &Function) -> Option<Symbol> {
+    let header = lexer::tokenize_line(lines.get(func.line)?, func.line).ok()?;
+    let name = header.get(1)?.clone();
+    // parameters are taken from the function, their places from the header
+    let params = func
+        .params
+        .iter()
+        .map(|p| header[2..].iter().find(|w| w.text == p.value).cloned())
+        .collect::<Option<_>>()?;
+    let mut res = Symbol {
+        name,
+        params,
+        locals: vec![],
+        end: func.root.end,
+    };
+    collect_locals(lines, &func.root, &mut res);
+    Some(res)
+}
+
+fn collect_locals(lines: &[String], node: &Node, symbol: &mut Symbol) {
+    for stmt in &node.stmts {
+        match stmt {
+            Statement::Assign { var, line, .. } if symbol.variable(&var.value).is_none() => {
+                let words = lines
+                    .get(*line)
+                    .and_then(|v| lexer::tokenize_line(v, *line).ok());
+                if let Some(var) = words.and_then(|v| v.into_iter().nth(1)) {
+                    symbol.locals.push(var);
+                }
+            }
+            Statement::Cond { child, .. } | Statement::Loop { child, .. } => {
+                collect_locals(lines, child, symbol)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Functions of a document in source order, as loaded by the interpreter,
+/// or from the outline if the document fails to load.
+fn symbols(dialect: Dialect, lines: &[String]) -> Vec<Symbol> {
+    let mut prog = Program::new();
+    prog.set_dialect(dialect);
+    if prog.load(&lines.join("\n")).is_err() {
+        return outline(lines);
+    }
+    let mut res: Vec<_> = prog
+        .funcs
+        .values()
+        .filter_map(|func| function_symbol(lines, func))
+        .collect();
+    res.sort_by_key(|v| v.name.span.line);
+    res
+}
+
+/// Describes what a built-in operator yields.
+fn describe_builtin(op: &str) -> &'static str {
+    match op {
+        "scan" => "reads the next integer from input",
+        "eof" => "yields 1 if nothing is left in input, 0 otherwise",
+        "+" => "yields the sum of a and b",
+        "-" => "yields a subtracted by b",
+        "*" => "yields the product of a and b",
+        "%" => "yields the non-negative remainder of a divided by |b|, or 0 if b is 0",
+        "/" => "yields (a - a % b) / |b|, or 0 if b is 0",
+        "==" => "yields 1 if a equals b, 0 otherwise",
+        "<" => "yields 1 if a is less than b, 0 otherwise",
+        ">" => "yields 1 if a is greater than b, 0 otherwise",
+        "<=" => "yields 1 if a is less than or equal to b, 0 otherwise",
+        ">=" => "yields 1 if a is greater than or equal to b, 0 otherwise",
+        "!=" => "yields 1 if a differs from b, 0 otherwise",
+        "and" => "yields 1 if neither a nor b is 0, 0 otherwise",
+        "or" => "yields 1 if either a or b is not 0, 0 otherwise",
+        "xor" => "yields 1 if exactly one of a and b is 0, 0 otherwise",
+        "not" => "yields 1 if a is 0, 0 otherwise",
+        _ => "",
+    }
+}
+
+fn builtin_signature(op: &str) -> String {
+    let cnt = builtin_arity(op).unwrap_or_default();
+    ["a", "b"]
+        .iter()
+        .take(cnt)
+        .fold(String::from(op), |res, v| res + " " + v)
+}
+
+/// Converts a column counted in characters to UTF-16 code units.
+fn to_utf16(line: &str, col: usize) -> usize {
+    line.chars().take(col).map(char::len_utf16).sum()
+}
+
+fn from_utf16(line: &str, col: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.chars().enumerate() {
+        if units >= col {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.chars().count()
+}
+
+fn position(lines: &[String], line: usize, col: usize) -> Value {
+    let text = lines.get(line).map_or("", |v| v.as_str());
+    Value::object(vec![
+        ("line", line.into()),
+        ("character", to_utf16(text, col).into()),
+    ])
+}
+
+fn span_range(lines: &[String], lexeme: &Lexeme) -> Value {
+    let span = lexeme.span;
+    Value::object(vec![
+        ("start", position(lines, span.line, span.col)),
+        ("end", position(lines, span.line, span.col + span.len)),
+    ])
+}
+
+/// Range covering a whole line, apart from surrounding whitespace.
+fn line_range(lines: &[String], line: usize) -> Value {
+    let text = lines.get(line).map_or("", |v| v.as_str());
+    let start = text.chars().take_while(|c| c.is_ascii_whitespace()).count();
+    let end = text.trim_end().chars().count().max(start);
+    Value::object(vec![
+        ("start", position(lines, line, start)),
+        ("end", position(lines, line, end)),
+    ])
+}
+
+fn diagnostic(lines: &[String], line: usize, severity: i64, code: &str, message: &str) -> Value {
+    let mut pairs = vec![
+        ("range", line_range(lines, line)),
+        ("severity", severity.into()),
+        ("source", "nhotyp".into()),
+        ("message", message.into()),
+    ];
+    if !code.is_empty() {
+        pairs.push(("code", code.into()));
+    }
+    Value::object(pairs)
+}
+
+fn diagnostics(dialect: Dialect, lines: &[String]) -> Vec<Value> {
+    let mut prog = Program::new();
+    prog.set_dialect(dialect);
+    match prog.load(&lines.join("\n")) {
+        Err(err) => {
+            // unclosed blocks are only noticed at the very end
+            let line = match err {
+                Error::UnclosedBlock => lines
+                    .iter()
+                    .rposition(|v| !v.trim().is_empty())
+                    .unwrap_or_default(),
+                _ => err.line(),
+            };
+            vec![diagnostic(lines, line, SEVERITY_ERROR, "", &err.format())]
+        }
+        Ok(()) => lint::lint(&prog, &[])
+            .iter()
+            .map(|w| diagnostic(lines, w.line, SEVERITY_WARNING, w.lint.code(), &w.message))
+            .collect(),
+    }
+}
+
+struct Server {
+    dialect: Dialect,
+    docs: HashMap<String, Vec<String>>,
+}
+
+impl Server {
+    /// Finds the document and the lexeme a request's position points at.
+    fn lexeme_at(&self, params: &Value) -> Option<(&[String], Lexeme)> {
+        let uri = params.get("textDocument").get("uri").as_str()?;
+        let lines = self.docs.get(uri)?;
+        let line = params.get("position").get("line").as_i64()? as usize;
+        let text = lines.get(line)?;
+        let col = params.get("position").get("character").as_i64()? as usize;
+        let col = from_utf16(text, col);
+        let lexeme = lexer::tokenize_line(text, line)
+            .ok()?
+            .into_iter()
+            .find(|w| w.span.col <= col && col <= w.span.col + w.span.len)
+            .filter(|w| w.kind != Kind::Comment)?;
+        Some((lines, lexeme))
+    }
+
+    fn publish(&self, uri: &str, output: &mut dyn Write) -> io::Result<()> {
+        let diagnostics = match self.docs.get(uri) {
+            Some(lines) => diagnostics(self.dialect, lines),
+            None => vec![],
+        };
+        let params = Value::object(vec![
+            ("uri", uri.into()),
+            ("diagnostics", diagnostics.into()),
+        ]);
+        json::write_message(
+            output,
+            &Value::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("method", "textDocument/publishDiagnostics".into()),
+                ("params", params),
+            ]),
+        )
+    }
+
+    fn initialize(&self) -> Value {
+        let capabilities = Value::object(vec![
+            ("textDocumentSync", 1i64.into()),
+            ("definitionProvider", true.into()),
+            ("hoverProvider", true.into()),
+            ("completionProvider", Value::object(vec![])),
+        ]);
+        Value::object(vec![
+            ("capabilities", capabilities),
+            ("serverInfo", Value::object(vec![("name", "nhotyp".into())])),
+        ])
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (lines, lexeme) = match self.lexeme_at(params) {
+            Some((lines, lexeme)) if lexeme.kind == Kind::Ident => (lines, lexeme),
+            _ => return Value::Null,
+        };
+        // variables belong to the enclosing function, names to the program
+        let line = lexeme.span.line;
+        let symbols = symbols(self.dialect, lines);
+        let target = symbols
+            .iter()
+            .find(|s| s.name.span.line <= line && line <= s.end)
+            .and_then(|s| s.variable(&lexeme.text))
+            .or_else(|| {
+                symbols
+                    .iter()
+                    .map(|s| &s.name)
+                    .find(|v| v.text == lexeme.text)
+            });
+        match target {
+            Some(v) => Value::object(vec![
+                ("uri", params.get("textDocument").get("uri").clone()),
+                ("range", span_range(lines, v)),
+            ]),
+            None => Value::Null,
+        }
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (lines, lexeme) = match self.lexeme_at(params) {
+            Some(v) => v,
+            None => return Value::Null,
+        };
+        let line = lexeme.span.line;
+        let symbols = symbols(self.dialect, lines);
+        let enclosing = symbols
+            .iter()
+            .find(|s| s.name.span.line <= line && line <= s.end);
+        let text = &lexeme.text;
+        let value = if builtin_arity(text).is_some() {
+            format!(
+                "```\n{}\n```\nBuilt-in operator, {}.",
+                builtin_signature(text),
+                describe_builtin(text)
+            )
+        } else if let Some(func) = symbols.iter().find(|s| s.name.text == *text) {
+            let cnt = func.params.len();
+            format!(
+                "```\n{}\n```\nTakes {} argument{}.",
+                func.signature(),
+                cnt,
+                if cnt == 1 { "" } else { "s" }
+            )
+        } else if let Some(func) = enclosing.filter(|s| s.variable(text).is_some()) {
+            let kind = match func.params.iter().any(|v| v.text == *text) {
+                true => "Parameter",
+                false => "Variable",
+            };
+            format!("{} of `{}`.", kind, func.name.text)
+        } else {
+            return Value::Null;
+        };
+        Value::object(vec![
+            (
+                "contents",
+                Value::object(vec![("kind", "markdown".into()), ("value", value.into())]),
+            ),
+            ("range", span_range(lines, &lexeme)),
+        ])
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let lines = match uri.and_then(|v| self.docs.get(v)) {
+            Some(v) => v,
+            None => return Value::Array(vec![]),
+        };
+        let line = params.get("position").get("line").as_i64().unwrap_or(0) as usize;
+        let item = |label: &str, kind: i64, detail: String| {
+            Value::object(vec![
+                ("label", label.into()),
+                ("kind", kind.into()),
+                ("detail", detail.into()),
+            ])
+        };
+        let mut res = vec![];
+        let symbols = symbols(self.dialect, lines);
+        // variables are only visible within their own function
+        let enclosing = symbols
+            .iter()
+            .find(|s| s.name.span.line <= line && line <= s.end);
+        if let Some(func) = enclosing {
+            for var in func.params.iter().chain(&func.locals) {
+                let detail = format!("variable of {}", func.name.text);
+                res.push(item(&var.text, COMPLETION_VARIABLE, detail));
+            }
+        }
+        for func in &symbols {
+            res.push(item(&func.name.text, COMPLETION_FUNCTION, func.signature()));
+        }
+        for word in lexer::KEYWORDS.iter().chain(lexer::OPERATORS) {
+            if builtin_arity(word).is_some() {
+                let detail = builtin_signature(word);
+                res.push(item(word, COMPLETION_OPERATOR, detail));
+            } else if *word != "=" {
+                res.push(item(word, COMPLETION_KEYWORD, String::from("keyword")));
+            }
+        }
+        Value::Array(res)
+    }
+}
+
+fn error(code: i64, message: String) -> Value {
+    Value::object(vec![("code", code.into()), ("message", message.into())])
+}
+
+/// Serves LSP requests until the client sends `exit` or closes the input.
+/// Returns the exit code, which is 1 unless `shutdown` was requested first.
+pub fn serve(dialect: Dialect, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<i32> {
+    let mut server = Server {
+        dialect,
+        docs: HashMap::new(),
+    };
+    let mut shutdown = false;
+    loop {
+        let msg = match json::read_message(input) {
+            Ok(Some(v)) => v,
+            Ok(None) => break,
+            // the message was consumed whole, so the next one is intact
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                let reply = Value::object(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("id", Value::Null),
+                    ("error", error(PARSE_ERROR, err.to_string())),
+                ]);
+                json::write_message(output, &reply)?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        // responses to our own requests carry no method
+        let method = match msg.get("method").as_str() {
+            Some(v) => v,
+            None => continue,
+        };
+        let params = msg.get("params");
+        let uri = params.get("textDocument").get("uri").as_str();
+        let result = match method {
+            "initialize" => Some(server.initialize()),
+            "shutdown" => {
+                shutdown = true;
+                Some(Value::Null)
+            }
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str();
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    server.docs.insert(String::from(uri), split_lines(text));
+                    server.publish(uri, output)?;
+                }
+                None
+            }
+            "textDocument/didChange" => {
+                // full synchronization sends the whole text every time
+                let changes = params.get("contentChanges").as_array();
+                let text = changes.last().and_then(|v| v.get("text").as_str());
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    server.docs.insert(String::from(uri), split_lines(text));
+                    server.publish(uri, output)?;
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = uri {
+                    server.docs.remove(uri);
+                    server.publish(uri, output)?;
+                }
+                None
+            }
+            "textDocument/definition" => Some(server.definition(params)),
+            "textDocument/hover" => Some(server.hover(params)),
+            "textDocument/completion" => Some(server.completion(params)),
+            _ => None,
+        };
+        // notifications never receive an answer
+        let id = msg.get("id");
+        if id.is_null() {
+            continue;
+        }
+        let mut reply = vec![("jsonrpc", "2.0".into()), ("id", id.clone())];
+        match result {
+            Some(v) => reply.push(("result", v)),
+            None => {
+                let message = format!("unsupported method {:?}", method);
+                reply.push(("error", error(METHOD_NOT_FOUND, message)));
+            }
+        }
+        json::write_message(output, &Value::object(reply))?;
+    }
+    Ok(match shutdown {
+        true => 0,
+        false => 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(messages: &[&str]) -> (i32, Vec<Value>) {
+        let mut input = vec![];
+        for msg in messages {
+            json::write_message(&mut input, &Value::parse(msg).unwrap()).unwrap();
+        }
+        let mut output = vec![];
+        let code = serve(Dialect::Standard, &mut input.as_slice(), &mut output).unwrap();
+        let mut output = output.as_slice();
+        let mut replies = vec![];
+        while let Some(reply) = json::read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        (code, replies)
+    }
+
+    #[test]
+    fn exits_cleanly_after_shutdown() {
+        let (code, replies) = session(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].get("result").is_null());
+    }
+
+    #[test]
+    fn fails_to_exit_without_shutdown() {
+        let (code, _) = session(&[r#"{"jsonrpc":"2.0","method":"exit"}"#]);
+        assert_eq!(code, 1);
+        let (code, _) = session(&[]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn answers_malformed_messages() {
+        let mut input = b"Content-Length: 4\r\n\r\n{bad".to_vec();
+        let shutdown = r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#;
+        json::write_message(&mut input, &Value::parse(shutdown).unwrap()).unwrap();
+        let mut output = vec![];
+        serve(Dialect::Standard, &mut input.as_slice(), &mut output).unwrap();
+        let mut output = output.as_slice();
+        let error = json::read_message(&mut output).unwrap().unwrap();
+        assert!(error.get("id").is_null());
+        assert_eq!(error.get("error").get("code").as_i64(), Some(PARSE_ERROR));
+        let reply = json::read_message(&mut output).unwrap().unwrap();
+        assert_eq!(reply.get("id").as_i64(), Some(2));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let open = r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":
+            {"textDocument":{"uri":"file:///a.nh","text":"function main as\n    let X = 1\n"}}}"#;
+        let (_, replies) = session(&[open]);
+        let params = replies[0].get("params");
+        assert_eq!(params.get("uri").as_str(), Some("file:///a.nh"));
+        let diagnostic = &params.get("diagnostics").as_array()[0];
+        assert_eq!(
+            diagnostic.get("range").get("start").get("line").as_i64(),
+            Some(1)
+        );
+    }
+
+    const SOURCE: &str = "function add a b as\n    let c = + a b\n    return c\nend function\nfunction main as\n    let x = add 1 2\n    print x\n    return 0\nend function\n";
+
+    /// Opens a document and sends a request at a position in it.
+    fn request(text: &str, method: &str, line: usize, character: usize) -> Value {
+        let open = Value::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/didOpen".into()),
+            (
+                "params",
+                Value::object(vec![(
+                    "textDocument",
+                    Value::object(vec![("uri", "file:///a.nh".into()), ("text", text.into())]),
+                )]),
+            ),
+        ]);
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"{}","params":
+                {{"textDocument":{{"uri":"file:///a.nh"}},"position":{{"line":{},"character":{}}}}}}}"#,
+            method, line, character
+        );
+        let (_, mut replies) = session(&[&open.to_string(), &request]);
+        replies.pop().unwrap().get("result").clone()
+    }
+
+    fn hover(text: &str, line: usize, character: usize) -> String {
+        let result = request(text, "textDocument/hover", line, character);
+        String::from(
+            result
+                .get("contents")
+                .get("value")
+                .as_str()
+                .unwrap_or_default(),
+        )
+    }
+
+    fn labels(text: &str, line: usize) -> Vec<String> {
+        let result = request(text, "textDocument/completion", line, 0);
+        let items = result.as_array().iter();
+        items
+            .map(|v| String::from(v.get("label").as_str().unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn hovers_show_parameters_of_loaded_functions() {
+        assert_eq!(
+            hover(SOURCE, 5, 13),
+            "```\nfunction add a b as\n```\nTakes 2 arguments."
+        );
+        assert_eq!(hover(SOURCE, 1, 16), "Parameter of `add`.");
+        assert_eq!(hover(SOURCE, 2, 11), "Variable of `add`.");
+        assert_eq!(
+            hover(SOURCE, 1, 12),
+            "```\n+ a b\n```\nBuilt-in operator, yields the sum of a and b."
+        );
+        assert_eq!(hover(SOURCE, 6, 2), "");
+    }
+
+    #[test]
+    fn finds_definitions_in_loaded_functions() {
+        let result = request(SOURCE, "textDocument/definition", 2, 11);
+        let start = result.get("range").get("start");
+        assert_eq!(start.get("line").as_i64(), Some(1));
+        assert_eq!(start.get("character").as_i64(), Some(8));
+        let result = request(SOURCE, "textDocument/definition", 5, 13);
+        let start = result.get("range").get("start");
+        assert_eq!(start.get("line").as_i64(), Some(0));
+        assert_eq!(start.get("character").as_i64(), Some(9));
+    }
+
+    #[test]
+    fn completes_variables_in_scope() {
+        let labels = labels(SOURCE, 6);
+        assert!(labels.starts_with(&[
+            String::from("x"),
+            String::from("add"),
+            String::from("main")
+        ]));
+        assert!(!labels.contains(&String::from("c")));
+        assert!(labels.contains(&String::from("scan")));
+    }
+
+    #[test]
+    fn falls_back_to_the_outline_while_editing() {
+        // the unfinished line keeps the document from loading
+        let text = SOURCE.replace("    print x", "    let y = \n    print x");
+        assert_eq!(hover(&text, 1, 16), "Parameter of `add`.");
+        let labels = labels(&text, 7);
+        assert!(labels.starts_with(&[String::from("x"), String::from("y"), String::from("add")]));
+    }
+}
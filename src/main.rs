@@ -1,1107 +1,881 @@
-use std::collections::HashMap;
+use nhotyp::cases;
+use nhotyp::coverage::Coverage;
+use nhotyp::dap;
+use nhotyp::debugger::Debugger;
+use nhotyp::emit_c;
+use nhotyp::emit_python;
+use nhotyp::infix;
+use nhotyp::judge::{self, Judgement, Limits, Verdict};
+use nhotyp::lint::{self, Lint};
+use nhotyp::lsp;
+use nhotyp::profile::Profiler;
+use nhotyp::trace::Tracer;
+use nhotyp::{
+    format_runtime_err, formatter, split_lines, BufferIo, Dialect, Error, Hook, Io, Program,
+    RedirectIo, Session, StdIo,
+};
 use std::env;
-use std::error::Error as StdError;
-use std::fmt;
 use std::fs;
-use std::io::Write;
-use std::ops;
-
-///////////////////////////////////////////////////////////////////////////////
-/// Error handling
-
-#[derive(PartialEq, Eq)]
-enum Error {
-    IllegalChar { line: usize, value: char },
-    TokenTooLong { line: usize, value: usize },
-    UnknownToken { line: usize, value: String },
-    MalformedAssign { line: usize },
-    MalformedCond { line: usize },
-    MalformedLoop { line: usize },
-    MalformedRet { line: usize },
-    MalformedFunc { line: usize },
-    MalformedEnd { line: usize },
-    UnclosedBlock,
-    DuplicateToken { line: usize, value: String },
-    WildStatement { line: usize },
-    WildFunction { line: usize },
-    MisplacedRet { line: usize },
-    UndeclaredToken { line: usize, value: String },
-    BadExpression { line: usize },
-    InputError { line: usize, value: String },
-}
-
-impl Error {
-    pub fn debug(&self) -> String {
-        match self {
-            Self::IllegalChar { line, value } => format!("IllegalChar({}, {:?})", line, value),
-            Self::TokenTooLong { line, value } => format!("TokenTooLong({}, {})", line, value),
-            Self::UnknownToken { line, value } => format!("UnknownToken({}, {:?})", line, value),
-            Self::MalformedAssign { line } => format!("MalformedAssign({})", line),
-            Self::MalformedCond { line } => format!("MalformedCond({})", line),
-            Self::MalformedLoop { line } => format!("MalformedLoop({})", line),
-            Self::MalformedRet { line } => format!("MalformedRet({})", line),
-            Self::MalformedFunc { line } => format!("MalformedFunc({})", line),
-            Self::MalformedEnd { line } => format!("MalformedEnd({})", line),
-            Self::UnclosedBlock => format!("UnclosedBlock"),
-            Self::DuplicateToken { line, value } => {
-                format!("DuplicateToken({}, {:?})", line, value)
-            }
-            Self::WildStatement { line } => format!("WildStatement({})", line),
-            Self::WildFunction { line } => format!("WildFunction({})", line),
-            Self::MisplacedRet { line } => format!("MisplacedRet({})", line),
-            Self::UndeclaredToken { line, value } => {
-                format!("UndeclaredToken({}, {})", line, value)
-            }
-            Self::BadExpression { line } => format!("BadExpression({})", line),
-            Self::InputError { line, value } => format!("InputError({}, {:?})", line, value),
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Enough for the default call depth limit of the judge, even unoptimized.
+const JUDGE_STACK_SIZE: usize = 512 << 20;
+
+struct Options {
+    dialect: Dialect,
+    raw: bool,
+    debug: bool,
+    trace: bool,
+    trace_file: Option<String>,
+    profile: bool,
+    // file receiving collapsed stacks
+    profile_file: Option<String>,
+    // file standing in for stdin as program input
+    input: Option<String>,
+}
+
+impl Options {
+    fn program(&self) -> Program {
+        let mut prog = Program::new();
+        prog.set_dialect(self.dialect);
+        prog.set_raw(self.raw);
+        prog
+    }
+}
+
+fn main_run_file(opts: &Options, filename: &str) -> i32 {
+    let content = match fs::read_to_string(filename) {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("nhotyp: fatal error: {}: cannot read file", &filename);
+            eprintln!("nhotyp: fatal error: no input files");
+            eprintln!("interpretation terminated.");
+            return 1;
         }
-    }
-
-    pub fn format(&self) -> String {
-        match self {
-            Self::IllegalChar { value, .. } => {
-                format!("unexpected character {:?}", value)
-            }
-            Self::TokenTooLong { value, .. } => {
-                format!("token length exceeded ({} of 63)", value)
-            }
-            Self::UnknownToken { value, .. } => {
-                format!("unexpected statement token {:?}", value)
-            }
-            Self::MalformedAssign { .. } => {
-                format!("malformed assignment statement")
-            }
-            Self::MalformedCond { .. } => {
-                format!("malformed conditional statement")
-            }
-            Self::MalformedLoop { .. } => {
-                format!("malformed loop statement")
-            }
-            Self::MalformedRet { .. } => {
-                format!("malformed return statement")
-            }
-            Self::MalformedFunc { .. } => {
-                format!("bad function definition")
-            }
-            Self::MalformedEnd { .. } => {
-                format!("illegal code block end")
-            }
-            Self::UnclosedBlock => format!("code block unclosed"),
-            Self::DuplicateToken { value, .. } => {
-                format!("conflict token {:?}", value)
-            }
-            Self::WildStatement { .. } => {
-                format!("statements should appear in functions")
-            }
-            Self::WildFunction { .. } => {
-                format!("function should not appear in functions")
-            }
-            Self::MisplacedRet { .. } => {
-                format!("always return at end of function")
-            }
-            Self::UndeclaredToken { value, .. } => {
-                format!("token {:?} undeclared", value)
-            }
-            Self::BadExpression { .. } => {
-                format!("expression having misplaced tokens")
+    };
+    let mut io: Box<dyn Io> = match &opts.input {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(v) => Box::new(RedirectIo::new(&v)),
+            Err(_) => {
+                eprintln!("nhotyp: fatal error: {}: cannot read file", path);
+                eprintln!("interpretation terminated.");
+                return 1;
             }
-            Self::InputError { value, .. } => {
-                format!("invalid input {:?}", value)
+        },
+        None => Box::new(StdIo),
+    };
+    let mut trace_out: Option<Box<dyn Write>> = match (&opts.trace_file, opts.trace) {
+        (Some(path), _) => match fs::File::create(path) {
+            Ok(v) => Some(Box::new(BufWriter::new(v))),
+            Err(_) => {
+                eprintln!("nhotyp: fatal error: {}: cannot write file", path);
+                eprintln!("interpretation terminated.");
+                return 1;
             }
+        },
+        (None, true) => Some(Box::new(std::io::stderr())),
+        (None, false) => None,
+    };
+    let lines = split_lines(&content);
+    // debugger commands are always typed in on stdin
+    let mut console = StdIo;
+    let mut debugger = match opts.debug {
+        true => {
+            console.write_out("type \"help\" for a list of debugger commands\n");
+            Some(Debugger::new(&lines, &mut console))
         }
+        false => None,
+    };
+    let mut tracer = trace_out.as_mut().map(|v| Tracer::new(&lines, &mut **v));
+    let mut profiler = Profiler::new(&lines);
+    let mut hooks: Vec<&mut dyn Hook> = vec![];
+    if let Some(v) = &mut debugger {
+        hooks.push(v);
     }
-
-    pub fn line(&self) -> usize {
-        match self {
-            Self::IllegalChar { line, .. } => *line,
-            Self::TokenTooLong { line, .. } => *line,
-            Self::UnknownToken { line, .. } => *line,
-            Self::MalformedAssign { line, .. } => *line,
-            Self::MalformedCond { line, .. } => *line,
-            Self::MalformedLoop { line, .. } => *line,
-            Self::MalformedRet { line, .. } => *line,
-            Self::MalformedFunc { line, .. } => *line,
-            Self::MalformedEnd { line, .. } => *line,
-            Self::UnclosedBlock => 0,
-            Self::DuplicateToken { line, .. } => *line,
-            Self::WildStatement { line, .. } => *line,
-            Self::WildFunction { line, .. } => *line,
-            Self::MisplacedRet { line, .. } => *line,
-            Self::UndeclaredToken { line, .. } => *line,
-            Self::BadExpression { line, .. } => *line,
-            Self::InputError { line, .. } => *line,
-        }
-    }
-}
-
-impl fmt::Debug for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.debug())
-    }
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.format())
-    }
-}
-
-impl StdError for Error {
-    fn description(&self) -> &str {
-        "ParserError"
+    if let Some(v) = &mut tracer {
+        hooks.push(v);
     }
-
-    fn cause(&self) -> Option<&dyn StdError> {
-        None
+    if opts.profile || opts.profile_file.is_some() {
+        hooks.push(&mut profiler);
     }
-}
-
-///////////////////////////////////////////////////////////////////////////////
-/// Tokens and Expressions
-
-#[derive(Eq, PartialEq, Ord, PartialOrd, Hash)]
-struct Token {
-    value: String,
-}
-
-impl Token {
-    fn from(s: &str, ptr: usize, allow_const: bool) -> Result<Self, Error> {
-        if s.len() > 63 {
-            return Err(Error::TokenTooLong {
-                line: ptr,
-                value: s.len(),
-            });
-        }
-        let x: Vec<_> = s
-            .chars()
-            .filter(|c| match c {
-                '0'..='9' => !allow_const,
-                '<' | '=' | '>' => !allow_const,
-                '+' | '-' | '*' | '%' | '/' => !allow_const,
-                'a'..='z' => false,
-                '_' => false,
-                _ => true,
-            })
-            .collect();
-        match x.len() {
-            0 => Ok(Self {
-                value: String::from(s),
-            }),
-            _ => Err(Error::IllegalChar {
-                line: ptr,
-                value: x[0],
-            }),
+    // catch return value or errors
+    let mut prog = opts.program();
+    let res = prog
+        .load(&content)
+        .and_then(|_| prog.execute_hooked(&mut *io, &mut hooks));
+    // profiles cover failed runs as well
+    profiler.finish();
+    if opts.profile {
+        eprint!("{}", profiler.report());
+    }
+    if let Some(path) = &opts.profile_file {
+        if fs::write(path, profiler.collapsed()).is_err() {
+            eprintln!("nhotyp: fatal error: {}: cannot write file", path);
         }
     }
-
-    pub fn from_any(ptr: usize, s: &str) -> Result<Self, Error> {
-        Self::from(s, ptr, true)
-    }
-
-    pub fn from_var(ptr: usize, s: &str) -> Result<Self, Error> {
-        Self::from(s, ptr, false)
-    }
-}
-
-impl Clone for Token {
-    fn clone(&self) -> Self {
-        Self {
-            value: String::from(&self.value),
+    match res {
+        Ok(v) => (v & 0xffffffffi64) as i32,
+        Err(Error::Interrupted { .. }) => 1,
+        Err(err) => {
+            eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
+            1
         }
     }
 }
 
-impl fmt::Debug for Token {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.value)
-    }
-}
-
-struct Expr {
-    tokens: Vec<Token>,
-}
-
-impl fmt::Debug for Expr {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_fmt(format_args!("{:?}", self.tokens))
-    }
+fn main_ii_show_copyright() {
+    println!("Copyright (c) 2021 Geoffrey Tang");
+    println!("All lefts reversed.");
+    println!();
 }
 
-///////////////////////////////////////////////////////////////////////////////
-/// Statements and Nodes
-
-enum Statement {
-    Assign {
-        var: Token,
-        expr: Expr,
-        line: usize,
-    },
-    Cond {
-        expr: Expr,
-        child: Node,
-        line: usize,
-    },
-    Loop {
-        expr: Expr,
-        child: Node,
-        line: usize,
-    },
-    Print {
-        vars: Vec<Token>,
-        line: usize,
-    },
-    Ret {
-        expr: Expr,
-        line: usize,
-    },
-    Func {
-        name: Token,
-        params: Vec<Token>,
-        child: Node,
-        line: usize,
-    },
+fn main_ii_show_license() {
+    println!("MIT License");
+    println!();
+    println!("Copyright (c) 2021 Geoffrey Tang");
+    println!();
+    println!("Permission is hereby granted, free of charge, to any person obtaining a copy");
+    println!("of this software and associated documentation files (the \"Software\"), to deal");
+    println!("in the Software without restriction, including without limitation the rights");
+    println!("to use, copy, modify, merge, publish, distribute, sublicense, and/or sell");
+    println!("copies of the Software, and to permit persons to whom the Software is");
+    println!("furnished to do so, subject to the following conditions:");
+    println!();
+    println!("The above copyright notice and this permission notice shall be included in all");
+    println!("copies or substantial portions of the Software.");
+    println!();
+    println!("THE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR");
+    println!("IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,");
+    println!("FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE");
+    println!("AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER");
+    println!("LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,");
+    println!("OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE");
+    println!("SOFTWARE.");
+    println!();
 }
 
-impl Statement {
-    pub fn line(&self) -> usize {
-        *match self {
-            Self::Assign { line, .. } => line,
-            Self::Cond { line, .. } => line,
-            Self::Loop { line, .. } => line,
-            Self::Print { line, .. } => line,
-            Self::Ret { line, .. } => line,
-            Self::Func { line, .. } => line,
+fn main_interactive_interpreter(opts: &Options) {
+    // prepare execution unit (this is modifed on interaction)
+    let mut session = Session::new(opts.program());
+    // start parsing
+    let mut in_block = false;
+    loop {
+        // read input if possible
+        let mut inp_line = String::new();
+        print!("{}", if !in_block { ">>> " } else { "... " });
+        std::io::stdout().flush().expect("unable to flush stdout");
+        // reached EOF, gracefully exit
+        if std::io::stdin().read_line(&mut inp_line).is_err() {
+            break;
         }
-    }
-}
-
-impl fmt::Debug for Statement {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Self::Assign { var, expr, line } => {
-                f.write_fmt(format_args!("let({:?} <- {:?} @ {})", var, expr, line))
+        inp_line = String::from(inp_line.trim());
+        // not something that we should consider
+        if inp_line.is_empty() {
+            continue;
+        }
+        // additional information
+        if inp_line == "copyright" {
+            main_ii_show_copyright();
+            continue;
+        } else if inp_line == "license" {
+            main_ii_show_license();
+            continue;
+        }
+        // push and attempt to parse, check for errors
+        match session.feed(inp_line) {
+            Ok(()) => {
+                in_block = false;
             }
-            Self::Cond { expr, child, line } => {
-                f.write_fmt(format_args!("if({:?} => {:?} @ {})", expr, child, line))
+            Err(Error::UnclosedBlock) => {
+                in_block = true;
+                session.rewind();
             }
-            Self::Loop { expr, child, line } => {
-                f.write_fmt(format_args!("while({:?} => {:?} @ {})", expr, child, line))
+            Err(err) => {
+                in_block = false;
+                print!("{}", format_runtime_err(None, session.lines(), &err, 0));
+                session.rollback();
             }
-            Self::Print { vars, line } => f.write_fmt(format_args!("print({:?} @ {})", vars, line)),
-            Self::Ret { expr, line } => f.write_fmt(format_args!("ret({:?} @ {})", expr, line)),
-            Self::Func {
-                name,
-                params,
-                child,
-                line,
-            } => f.write_fmt(format_args!(
-                "def({:?} -> {:?} => {:?} @ {})",
-                name, params, child, line
-            )),
-        }
-    }
-}
-
-struct Node {
-    stmts: Vec<Statement>,
-}
-
-impl fmt::Debug for Node {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_fmt(format_args!("{:?}", self.stmts))
-    }
-}
-
-struct State<'a> {
-    lines: &'a mut Vec<String>,
-    ptr: usize,
-}
-
-type StmtParseResult = Result<Statement, Error>;
-
-fn parse_stmt_assign(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    // let <variable> = <expression>
-    let len = words.len();
-    if len < 4 {
-        return Err(Error::MalformedAssign { line: state.ptr - 1 });
-    }
-    let var = Token::from_var(state.ptr, words[1])?;
-    let mut tokens = vec![];
-    for i in 3..len {
-        tokens.push(Token::from_any(state.ptr, words[i])?);
-    }
-    Ok(Statement::Assign {
-        var,
-        expr: Expr { tokens },
-        line: state.ptr - 1,
-    })
-}
-
-fn parse_stmt_cond(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    // if <expression> then
-    //     <code block>
-    // end if
-    let len = words.len();
-    if words.len() < 3 || words[len - 1] != "then" {
-        return Err(Error::MalformedCond { line: state.ptr - 1 });
-    }
-    // generate expression
-    let mut tokens = vec![];
-    for i in 1..len - 1 {
-        tokens.push(Token::from_any(state.ptr, words[i])?);
+        };
     }
-    // get child node
-    Ok(Statement::Cond {
-        expr: Expr { tokens },
-        child: parse_node(state, "if")?,
-        line: state.ptr - 1,
-    })
+    println!("\n");
 }
 
-fn parse_stmt_loop(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    // while <expression> do
-    //     <code block>
-    // end while
-    let len = words.len();
-    if words.len() < 3 || words[len - 1] != "do" {
-        return Err(Error::MalformedLoop { line: state.ptr - 1 });
+fn main_fmt(args: &[String]) -> i32 {
+    let mut check = false;
+    let mut dialect = Dialect::Standard;
+    let mut files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--extended" => dialect = Dialect::Extended,
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
+            }
+            _ => files.push(arg),
+        }
     }
-    // generate expression
-    let mut tokens = vec![];
-    for i in 1..len - 1 {
-        tokens.push(Token::from_any(state.ptr, words[i])?);
+    // without files, act as a filter from stdin to stdout
+    if files.is_empty() {
+        let mut content = String::new();
+        if std::io::stdin().read_to_string(&mut content).is_err() {
+            eprintln!("nhotyp: fatal error: cannot read stdin");
+            return 1;
+        }
+        let res = match check {
+            true => formatter::is_formatted(&content, dialect).inspect(|&formatted| {
+                if !formatted {
+                    eprintln!("nhotyp: stdin: not formatted");
+                }
+            }),
+            false => formatter::format_source(&content, dialect).map(|v| {
+                print!("{}", v);
+                true
+            }),
+        };
+        return match res {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(err) => {
+                eprint!(
+                    "{}",
+                    format_runtime_err(None, &split_lines(&content), &err, 1)
+                );
+                1
+            }
+        };
     }
-    // get child node
-    Ok(Statement::Loop {
-        expr: Expr { tokens },
-        child: parse_node(state, "while")?,
-        line: state.ptr - 1,
-    })
-}
-
-fn parse_stmt_print(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    // print <var1> <var2> ... <varn>
-    // allows 0 variables
-    let mut vars = vec![];
-    for i in 1..words.len() {
-        vars.push(Token::from_var(state.ptr, words[i])?);
+    // otherwise format files in place
+    let mut status = 0;
+    for filename in files {
+        let content = match fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(_) => {
+                eprintln!("nhotyp: fatal error: {}: cannot read file", filename);
+                status = 1;
+                continue;
+            }
+        };
+        let res = match check {
+            true => formatter::is_formatted(&content, dialect).inspect(|&formatted| {
+                if !formatted {
+                    eprintln!("nhotyp: {}: not formatted", filename);
+                }
+            }),
+            false => formatter::format_source(&content, dialect).map(|v| {
+                // files already formatted are left untouched
+                if v != content && fs::write(filename, v).is_err() {
+                    eprintln!("nhotyp: fatal error: {}: cannot write file", filename);
+                    return false;
+                }
+                true
+            }),
+        };
+        match res {
+            Ok(true) => (),
+            Ok(false) => status = 1,
+            Err(err) => {
+                let lines = split_lines(&content);
+                eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
+                status = 1;
+            }
+        }
     }
-    Ok(Statement::Print {
-        vars,
-        line: state.ptr - 1,
-    })
-}
-
-fn parse_stmt_ret(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    // return <expression>
-    let len = words.len();
-    if len < 2 {
-        return Err(Error::MalformedRet { line: state.ptr - 1 });
+    status
+}
+
+fn main_lint(args: &[String]) -> i32 {
+    let mut dialect = Dialect::Standard;
+    let mut allowed = vec![];
+    let mut files = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--extended" => dialect = Dialect::Extended,
+            "--allow" => match iter.next().and_then(|v| Lint::find(v)) {
+                Some(v) => allowed.push(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --allow expects a lint code or name");
+                    return 1;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
+            }
+            _ => files.push(arg),
+        }
     }
-    let mut tokens = vec![];
-    for i in 1..len {
-        tokens.push(Token::from_any(state.ptr, words[i])?);
+    if files.is_empty() {
+        eprintln!("nhotyp: fatal error: no input files");
+        return 1;
+    }
+    let mut status = 0;
+    for filename in files {
+        let content = match fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(_) => {
+                eprintln!("nhotyp: fatal error: {}: cannot read file", filename);
+                status = 1;
+                continue;
+            }
+        };
+        let lines = split_lines(&content);
+        let mut prog = Program::new();
+        prog.set_dialect(dialect);
+        if let Err(err) = prog.load(&content) {
+            eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
+            status = 1;
+            continue;
+        }
+        for warning in lint::lint(&prog, &allowed) {
+            eprint!(
+                "{}",
+                lint::format_warning(Some(filename), &lines, &warning, 1)
+            );
+            status = 1;
+        }
     }
-    Ok(Statement::Ret {
-        expr: Expr { tokens },
-        line: state.ptr - 1,
-    })
+    status
 }
 
-fn parse_stmt_func(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    // function <name> <param1> <param2> ... <paramn> as
-    //     <code block>
-    // end function
-    let len = words.len();
-    if words.len() < 3 || words[len - 1] != "as" {
-        return Err(Error::MalformedFunc { line: state.ptr - 1 });
-    }
-    // parse parameters
-    let name = Token::from_var(state.ptr, words[1])?;
-    let mut params = vec![];
-    for i in 2..len - 1 {
-        let token = Token::from_var(state.ptr, words[i])?;
-        if is_reserved_kw(&token.value) {
-            return Err(Error::DuplicateToken {
-                line: state.ptr - 1,
-                value: token.value,
-            });
+fn main_lsp(args: &[String]) -> i32 {
+    let mut dialect = Dialect::Standard;
+    for arg in args {
+        match arg.as_str() {
+            "--extended" => dialect = Dialect::Extended,
+            _ => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
+            }
         }
-        params.push(token);
     }
-    // too many parameters
-    if params.len() > 16 {
-        return Err(Error::MalformedFunc { line: state.ptr - 1 });
+    // the protocol owns stdout, so errors may only go to stderr
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    match lsp::serve(dialect, &mut stdin.lock(), &mut stdout.lock()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("nhotyp: fatal error: {}", err);
+            1
+        }
     }
-    // get child node
-    Ok(Statement::Func {
-        name,
-        params,
-        child: parse_node(state, "function")?,
-        line: state.ptr - 1,
-    })
 }
 
-fn parse_stmt(state: &mut State, words: &Vec<&str>) -> StmtParseResult {
-    match words[0] {
-        "let" => parse_stmt_assign(state, &words),
-        "if" => parse_stmt_cond(state, &words),
-        "while" => parse_stmt_loop(state, &words),
-        "print" => parse_stmt_print(state, &words),
-        "return" => parse_stmt_ret(state, &words),
-        "function" => parse_stmt_func(state, &words),
-        _ => Err(Error::UnknownToken {
-            line: state.ptr - 1,
-            value: String::from(words[0].to_string()),
-        }),
+fn main_coverage(args: &[String]) -> i32 {
+    let mut dialect = Dialect::Standard;
+    let mut lcov = None;
+    let mut files = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--extended" => dialect = Dialect::Extended,
+            "--lcov" => match iter.next() {
+                Some(v) => lcov = Some(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --lcov expects a file name");
+                    return 1;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
+            }
+            _ => files.push(arg),
+        }
     }
-}
-
-fn parse_node(state: &mut State, term: &str) -> Result<Node, Error> {
-    let mut stmts = vec![];
-    let mut gracefully_ended = term.len() == 0;
-    // splitting words here to check for terminations
-    while state.ptr < state.lines.len() {
-        // eradicate comments
-        let mut line = state.lines[state.ptr].clone();
-        state.ptr += 1;
-        if line.contains('#') {
-            let splits: Vec<_> = line.split('#').collect();
-            line = String::from(splits[0]);
+    // the program comes first, followed by one input file per run
+    let (filename, inputs) = match files.split_first() {
+        Some(v) => v,
+        None => {
+            eprintln!("nhotyp: fatal error: no input files");
+            return 1;
         }
-        // filter into singular words and check if is empty line
-        let words: Vec<_> = line.split(' ').filter(|w| w.len() > 0).collect();
-        if words.len() == 0 {
-            continue;
+    };
+    let content = match fs::read_to_string(filename) {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("nhotyp: fatal error: {}: cannot read file", filename);
+            return 1;
         }
-        // 'end' statement triggers code block close
-        if words[0] == "end" {
-            if words.len() == 2 && words[1] == term {
-                gracefully_ended = true;
-                break;
+    };
+    let lines = split_lines(&content);
+    let mut prog = Program::new();
+    prog.set_dialect(dialect);
+    prog.set_raw(true);
+    if let Err(err) = prog.load(&content) {
+        eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
+        return 1;
+    }
+    // without input files, run once on stdin
+    let mut runs = vec![];
+    for path in inputs {
+        match fs::read_to_string(path) {
+            Ok(v) => runs.push((path.as_str(), v)),
+            Err(_) => {
+                eprintln!("nhotyp: fatal error: {}: cannot read file", path);
+                return 1;
             }
-            return Err(Error::MalformedEnd { line: state.ptr - 1 });
         }
-        // send statement to corresponding parser
-        stmts.push(parse_stmt(state, &words)?);
     }
-    // check if block is unterminated
-    if !gracefully_ended {
-        return Err(Error::UnclosedBlock);
-    }
-    // done node parsing
-    Ok(Node { stmts })
-}
-
-///////////////////////////////////////////////////////////////////////////////
-/// Variables
-
-const VARIABLE_LIMIT: i128 = 0x1_0000_0000_0000;
-
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
-struct Variable {
-    data: i128,
-}
-
-impl Variable {
-    fn from(val: i128) -> Self {
-        let mut data = val;
-        if data > 0 {
-            data = data & (VARIABLE_LIMIT - 1);
-        } else if data < 0 {
+    if inputs.is_empty() {
+        let mut input = String::new();
+        if std::io::stdin().read_to_string(&mut input).is_err() {
+            eprintln!("nhotyp: fatal error: cannot read stdin");
+            return 1;
         }
-        Self { data }
-    }
-}
-
-impl ops::Add for Variable {
-    type Output = Self;
-    fn add(self, other: Self) -> Self::Output {
-        Self::from(self.data + other.data)
-    }
-}
-
-impl ops::Sub for Variable {
-    type Output = Self;
-    fn sub(self, other: Self) -> Self::Output {
-        Self::from(self.data - other.data)
-    }
-}
-
-impl ops::Mul for Variable {
-    type Output = Self;
-    fn mul(self, other: Self) -> Self::Output {
-        Self::from(self.data * other.data)
-    }
-}
-
-impl ops::Rem for Variable {
-    type Output = Self;
-    fn rem(self, other: Self) -> Self::Output {
-        let a = self.data;
-        let b = other.data.abs();
-        if b == 0 {
-            return Self::from(0);
+        runs.push(("stdin", input));
+    }
+    let mut coverage = Coverage::new(&prog);
+    let mut status = 0;
+    for (path, input) in runs {
+        let mut io = BufferIo::new(&input);
+        if let Err(err) = prog.execute_hooked(&mut io, &mut coverage) {
+            eprintln!("nhotyp: run with input {} failed", path);
+            eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
+            status = 1;
         }
-        Self::from(match a > 0 {
-            true => a % b,
-            false => (b - (-a) % b) % b,
-        })
     }
-}
-
-impl ops::Div for Variable {
-    type Output = Self;
-    fn div(self, other: Self) -> Self::Output {
-        let b = other.data.abs();
-        if b == 0 {
-            return Self::from(0);
+    print!("{}", coverage.annotate(&lines));
+    if let Some(path) = lcov {
+        if fs::write(path, coverage.lcov(filename)).is_err() {
+            eprintln!("nhotyp: fatal error: {}: cannot write file", path);
+            status = 1;
         }
-        Self::from((self - self % other).data / b)
-    }
-}
-
-impl ops::BitAnd for Variable {
-    type Output = bool;
-    fn bitand(self, other: Self) -> bool {
-        self.data != 0 && other.data != 0
-    }
-}
-
-impl ops::BitOr for Variable {
-    type Output = bool;
-    fn bitor(self, other: Self) -> bool {
-        self.data != 0 || other.data != 0
-    }
-}
-
-impl ops::BitXor for Variable {
-    type Output = bool;
-    fn bitxor(self, other: Self) -> bool {
-        (self.data != 0) ^ (other.data != 0)
     }
+    status
 }
 
-impl ops::Not for Variable {
-    type Output = bool;
-    fn not(self) -> bool {
-        self.data == 0
-    }
-}
-
-impl fmt::Debug for Variable {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_fmt(format_args!("{:?}", self.data))
-    }
-}
-
-///////////////////////////////////////////////////////////////////////////////
-/// Program execution
-
-struct Function {
-    params: Vec<Token>,
-    root: Node,
-    line: usize,
-}
-
-struct Program {
-    funcs: HashMap<Token, Function>,
-}
-
-struct RunInstance<'a> {
-    prog: &'a Program,
-    scope: HashMap<Token, Variable>,
-}
-
-fn eval_expr_func(
-    instance: &mut RunInstance,
-    expr: &Expr,
-    ptr: &mut usize,
-    line: usize,
-) -> Result<Variable, Error> {
-    // detect out-of-bounds error
-    if *ptr >= expr.tokens.len() {
-        return Err(Error::BadExpression { line: line });
+fn main_test(args: &[String]) -> i32 {
+    let mut dialect = Dialect::Standard;
+    let mut files = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--extended" => dialect = Dialect::Extended,
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
+            }
+            _ => files.push(arg),
+        }
     }
-    // retrieve function parameter count
-    let op_token: &str = &expr.tokens[*ptr].value;
-    let op_cnt = match op_token {
-        "scan" => 0,
-        "+" | "-" | "*" | "%" | "/" => 2,
-        "==" | "<" | ">" | "<=" | ">=" | "!=" => 2,
-        "and" | "or" | "xor" => 2,
-        "not" => 1,
+    // without a directory of cases, the program's own tests are run
+    let (filename, dir) = match files.as_slice() {
+        [filename] => (filename, None),
+        [filename, dir] => (filename, Some(dir)),
         _ => {
-            // parse constant first
-            if let Ok(v) = op_token.parse() {
-                return Ok(Variable::from(v));
-            }
-            let token = Token::from_var(line, op_token)?;
-            if instance.scope.contains_key(&token) {
-                // variable takes precedence
-                return Ok(instance.scope[&token].clone());
-            } else if instance.prog.funcs.contains_key(&token) {
-                // then attempt to call function
-                instance.prog.funcs[&token].params.len()
-            } else {
-                // and nothing else
-                return Err(Error::UndeclaredToken {
-                    line: line,
-                    value: String::from(&token.value),
-                });
-            }
+            eprintln!("nhotyp: fatal error: expected a program and a directory of cases");
+            return 1;
+        }
+    };
+    let content = match fs::read_to_string(filename) {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("nhotyp: fatal error: {}: cannot read file", filename);
+            return 1;
         }
     };
-    // parse parameters
-    let mut params = vec![];
-    for _ in 0..op_cnt {
-        *ptr += 1;
-        params.push(eval_expr_func(instance, expr, ptr, line)?);
+    let lines = split_lines(&content);
+    let mut prog = Program::new();
+    prog.set_dialect(dialect);
+    prog.set_raw(true);
+    if let Err(err) = prog.load(&content) {
+        eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
+        return 1;
+    }
+    let dir = match dir {
+        Some(v) => v,
+        None => return run_tests(filename, &lines, &prog),
+    };
+    let cases = match cases::load_cases(Path::new(dir)) {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("nhotyp: fatal error: {}: cannot read cases", dir);
+            return 1;
+        }
+    };
+    let mut failed = 0;
+    for case in &cases {
+        let outcome = cases::run_case(&prog, case, &mut ());
+        let ms = outcome.time.as_secs_f64() * 1000.0;
+        // explain failures right below their case
+        let details = match (&outcome.result, &case.expected) {
+            (Err(err), _) => format_runtime_err(Some(filename), &lines, err, 1),
+            (Ok(_), None) => format!("{}.out: cannot read file\n", case.name),
+            (Ok(_), Some(v)) if !cases::same_output(&outcome.stdout, v) => {
+                cases::diff(&outcome.stdout, v)
+            }
+            (Ok(_), Some(_)) => {
+                println!("case {} ... ok ({:.3} ms)", case.name, ms);
+                continue;
+            }
+        };
+        failed += 1;
+        println!("case {} ... FAILED ({:.3} ms)", case.name, ms);
+        for line in details.lines() {
+            println!("    {}", line);
+        }
     }
-    // evaluate result
-    let v = &params;
-    let is = |i: usize| -> bool { v[i].data != 0 };
-    Ok(match op_token {
-        "scan" => {
-            let mut inp = String::new();
-            print!("  > ");
-            std::io::stdout().flush().expect("unable to flush stdout");
-            if let Err(_) = std::io::stdin().read_line(&mut inp) {
-                return Err(Error::InputError {
-                    line,
-                    value: String::from("null"),
-                });
+    let verdict = if failed == 0 { "ok" } else { "FAILED" };
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed",
+        verdict,
+        cases.len() - failed,
+        failed
+    );
+    (failed > 0) as i32
+}
+
+fn main_judge(args: &[String]) -> i32 {
+    let mut dialect = Dialect::Standard;
+    let mut limits = Limits::default();
+    let mut report = None;
+    let mut files = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--extended" => dialect = Dialect::Extended,
+            "--steps" | "--time-limit" | "--depth" => {
+                let value = match iter.next().and_then(|v| v.parse::<u64>().ok()) {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("nhotyp: fatal error: {} expects a number", arg);
+                        return 1;
+                    }
+                };
+                match arg.as_str() {
+                    "--steps" => limits.steps = value,
+                    "--time-limit" => limits.time = Duration::from_millis(value),
+                    _ => limits.depth = value as usize,
+                }
             }
-            inp = String::from(inp.trim());
-            match inp.parse() {
-                Ok(v) => Variable::from(v),
-                Err(_) => return Err(Error::InputError { line, value: inp }),
+            "--report" => match iter.next() {
+                Some(v) => report = Some(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --report expects a file name");
+                    return 1;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
             }
+            _ => files.push(arg),
         }
-        "+" => v[0] + v[1],
-        "-" => v[0] - v[1],
-        "*" => v[0] * v[1],
-        "%" => v[0] % v[1],
-        "/" => v[0] / v[1],
-        "==" => Variable::from(if v[0] == v[1] { 1 } else { 0 }),
-        "<" => Variable::from(if v[0] < v[1] { 1 } else { 0 }),
-        ">" => Variable::from(if v[0] > v[1] { 1 } else { 0 }),
-        "<=" => Variable::from(if v[0] <= v[1] { 1 } else { 0 }),
-        ">=" => Variable::from(if v[0] >= v[1] { 1 } else { 0 }),
-        "!=" => Variable::from(if v[0] != v[1] { 1 } else { 0 }),
-        "and" => Variable::from(if is(0) && is(1) { 1 } else { 0 }),
-        "or" => Variable::from(if is(0) || is(1) { 1 } else { 0 }),
-        "xor" => Variable::from(if is(0) != is(1) { 1 } else { 0 }),
-        "not" => Variable::from(if is(0) { 0 } else { 1 }),
+    }
+    let (filename, dir) = match files.as_slice() {
+        [filename, dir] => (filename, dir),
         _ => {
-            let token = Token::from_var(line, op_token)?;
-            call_function(instance.prog, &token, params, line)?
+            eprintln!("nhotyp: fatal error: expected a program and a directory of cases");
+            return 1;
         }
-    })
-}
-
-fn eval_expr(instance: &mut RunInstance, expr: &Expr, from_line: usize) -> Result<Variable, Error> {
-    let mut ptr = 0;
-    let res = eval_expr_func(instance, expr, &mut ptr, from_line)?;
-    if ptr + 1 < expr.tokens.len() {
-        return Err(Error::BadExpression { line: from_line });
-    }
-    Ok(res)
-}
-
-fn is_reserved_kw(token: &str) -> bool {
-    match token {
-        "and" | "or" | "xor" | "not" | "scan" => true,
-        "let" => true,
-        "if" | "then" => true,
-        "while" | "do" => true,
-        "function" | "as" | "return" => true,
-        "end" => true,
-        "print" => true,
-        _ => false,
-    }
-}
-
-fn exec_statement(instance: &mut RunInstance, stmt: &Statement) -> Result<(), Error> {
-    match &stmt {
-        &Statement::Assign { var, expr, line } => {
-            if is_reserved_kw(&var.value) || instance.prog.funcs.contains_key(&var) {
-                return Err(Error::DuplicateToken {
-                    line: *line,
-                    value: String::from(&var.value),
-                });
-            }
-            let res = eval_expr(instance, &expr, *line)?;
-            instance.scope.insert(var.clone(), res);
+    };
+    let content = match fs::read_to_string(filename) {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("nhotyp: fatal error: {}: cannot read file", filename);
+            return 1;
         }
-        &Statement::Cond { expr, child, line } => {
-            let cond = eval_expr(instance, &expr, *line)?;
-            if cond.data != 0 {
-                exec_node(instance, &child)?;
-            }
+    };
+    let cases = match cases::load_cases(Path::new(dir)) {
+        Ok(v) => v,
+        Err(_) => {
+            eprintln!("nhotyp: fatal error: {}: cannot read cases", dir);
+            return 1;
         }
-        &Statement::Loop { expr, child, line } => loop {
-            let cond = eval_expr(instance, &expr, *line)?;
-            if cond.data == 0 {
-                break;
-            }
-            exec_node(instance, &child)?;
-        },
-        &Statement::Print { vars, line } => {
-            // collect values
-            let mut vals = vec![];
-            for var in vars {
-                if !instance.scope.contains_key(var) {
-                    return Err(Error::UndeclaredToken {
-                        line: *line,
-                        value: String::from(&var.value),
-                    });
-                }
-                vals.push(instance.scope[&var].data);
-            }
-            // flush into stdout in one go
-            print!("   ");
-            for val in vals {
-                print!(" {}", val);
-            }
-            println!("");
-            std::io::stdout().flush().expect("unable to flush stdout");
+    };
+    let mut prog = Program::new();
+    prog.set_dialect(dialect);
+    prog.set_raw(true);
+    // a submission failing to load fails every case alike
+    let results: Vec<_> = match prog.load(&content) {
+        Ok(_) => cases
+            .iter()
+            .map(|case| judge::judge_case(&prog, case, limits))
+            .collect(),
+        Err(err) => cases
+            .iter()
+            .map(|case| Judgement::compile_error(case, &err))
+            .collect(),
+    };
+    for res in &results {
+        let ms = res.time.as_secs_f64() * 1000.0;
+        println!(
+            "case {} ... {} ({:.3} ms, {} steps)",
+            res.name,
+            res.verdict.code(),
+            ms,
+            res.steps
+        );
+        for line in res.detail.lines() {
+            println!("    {}", line);
         }
-        &Statement::Ret { line, .. } => return Err(Error::MisplacedRet { line: *line }),
-        &Statement::Func { line, .. } => return Err(Error::WildFunction { line: *line }),
     }
-    Ok(())
-}
-
-fn exec_node(instance: &mut RunInstance, node: &Node) -> Result<(), Error> {
-    for stmt in &node.stmts {
-        match stmt {
-            _ => exec_statement(instance, &stmt)?,
+    let verdict = judge::overall(&results);
+    let accepted = results
+        .iter()
+        .filter(|v| v.verdict == Verdict::Accepted)
+        .count();
+    println!();
+    println!(
+        "verdict: {} ({}), score {:.1} ({} of {} accepted)",
+        verdict.code(),
+        verdict.name(),
+        judge::score(&results),
+        accepted,
+        results.len()
+    );
+    if let Some(path) = report {
+        let text = judge::report(filename, limits, &results).to_string() + "\n";
+        if fs::write(path, text).is_err() {
+            eprintln!("nhotyp: fatal error: {}: cannot write file", path);
+            return 1;
         }
     }
-    Ok(())
+    (verdict != Verdict::Accepted) as i32
 }
 
-fn call_function(
-    prog: &Program,
-    token: &Token,
-    params: Vec<Variable>,
-    from_line: usize,
-) -> Result<Variable, Error> {
-    // lookup function
-    if !prog.funcs.contains_key(&token) {
-        return Err(Error::UndeclaredToken {
-            line: from_line,
-            value: String::from(&token.value),
-        });
-    }
-    let func = &prog.funcs[&token];
-    // generate instance
-    let scope = HashMap::new();
-    let mut instance = RunInstance { prog, scope };
-    // put parameters into scope
-    for i in 0..params.len() {
-        let key = func.params[i].clone();
-        let value = params[i].clone();
-        if prog.funcs.contains_key(&key) {
-            return Err(Error::DuplicateToken {
-                line: func.line,
-                value: key.value,
-            });
+fn run_tests(filename: &str, lines: &[String], prog: &Program) -> i32 {
+    let tests = prog.tests();
+    let mut failed = 0;
+    for name in &tests {
+        let mut io = BufferIo::new("");
+        let start = Instant::now();
+        let res = prog.run_test(&mut io, name);
+        let ms = start.elapsed().as_secs_f64() * 1000.0;
+        let err = match res {
+            Ok(_) => {
+                println!("test {} ... ok ({:.3} ms)", name, ms);
+                continue;
+            }
+            Err(err) => err,
+        };
+        failed += 1;
+        println!("test {} ... FAILED ({:.3} ms)", name, ms);
+        // output leading up to the failure helps telling what went wrong
+        let details = io.stdout + &format_runtime_err(Some(filename), lines, &err, 1);
+        for line in details.lines() {
+            println!("    {}", line);
         }
-        instance.scope.insert(key, value);
-    }
-    // iterate function statements
-    let stmts = &func.root.stmts;
-    if stmts.len() < 1 {
-        return Err(Error::MisplacedRet { line: func.line });
     }
-    for i in 0..stmts.len() - 1 {
-        exec_statement(&mut instance, &stmts[i])?;
-    }
-    // last statement must return value
-    match &stmts[stmts.len() - 1] {
-        Statement::Ret { expr, .. } => eval_expr(&mut instance, &expr, from_line),
-        _ => Err(Error::MisplacedRet { line: func.line }),
-    }
-}
-
-fn format_runtime_err(
-    filename: Option<&str>,
-    lines: &Vec<String>,
-    err: &Error,
-    line_offset: usize,
-) -> String {
-    let line = err.line();
-    let header = match filename {
-        Some(v) => format!("{}:{}: error: ", v, line + line_offset),
-        None => format!("stdin:{}: error: ", line + line_offset),
-    };
-    let padding: String = (2..header.len()).map(|_| ' ').collect();
-    let line = if let Some(v) = lines.get(line) { v } else { "" };
-    format!("{}{}\n{}> {}\n", header, err, padding, line.trim())
-}
-
-fn execute_program(content: &mut Vec<String>) -> Result<i64, Error> {
-    // parse file for functions
-    let mut state = State {
-        lines: content,
-        ptr: 0,
-    };
-    let node = parse_node(&mut state, "")?;
-    // check for wild statements at global scope and construct program
-    let mut prog = Program {
-        funcs: HashMap::new(),
-    };
-    for stmt in node.stmts {
-        if let Statement::Func {
-            name,
-            params,
-            child,
-            line,
-        } = stmt
-        {
-            if is_reserved_kw(&name.value) || prog.funcs.contains_key(&name) {
-                return Err(Error::DuplicateToken {
-                    line,
-                    value: name.value,
-                });
+    let verdict = if failed == 0 { "ok" } else { "FAILED" };
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed",
+        verdict,
+        tests.len() - failed,
+        failed
+    );
+    (failed > 0) as i32
+}
+
+/// Loads a program for translating it.
+fn load(source: &str, dialect: Dialect) -> Result<Program, Error> {
+    let mut prog = Program::new();
+    prog.set_dialect(dialect);
+    prog.load(source)?;
+    Ok(prog)
+}
+
+fn main_emit(args: &[String], emit: fn(&str, Dialect) -> Result<String, Error>) -> i32 {
+    let mut dialect = Dialect::Standard;
+    let mut output = None;
+    let mut files = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--extended" => dialect = Dialect::Extended,
+            "--output" => match iter.next() {
+                Some(v) => output = Some(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --output expects a file name");
+                    return 1;
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                return 1;
             }
-            prog.funcs.insert(
-                Token {
-                    value: String::from(&name.value),
-                },
-                Function {
-                    params,
-                    root: child,
-                    line,
-                },
-            );
-        } else {
-            return Err(Error::WildStatement { line: stmt.line() });
+            _ => files.push(arg),
         }
     }
-    // check if main function exists and call
-    let main_token = Token {
-        value: String::from("main"),
+    let filename = match files.as_slice() {
+        [filename] => filename,
+        _ => {
+            eprintln!("nhotyp: fatal error: expected a single program");
+            return 1;
+        }
     };
-    Ok(call_function(&mut prog, &main_token, vec![], 0)?.data as i64)
-}
-
-fn main_run_file(filename: &str) -> i32 {
-    let content;
-    match fs::read_to_string(&filename) {
-        Ok(v) => content = v,
+    let content = match fs::read_to_string(filename) {
+        Ok(v) => v,
         Err(_) => {
-            eprintln!("nhotyp: fatal error: {}: cannot read file", &filename);
-            eprintln!("nhotyp: fatal error: no input files");
-            eprintln!("interpretation terminated.");
+            eprintln!("nhotyp: fatal error: {}: cannot read file", filename);
             return 1;
         }
-    }
-    let mut lines = content.split('\n').map(|s| String::from(s)).collect();
-    // catch return value or errors
-    match execute_program(&mut lines) {
-        Ok(v) => (v & 0xffffffffi64) as i32,
+    };
+    let lines = split_lines(&content);
+    let res = match emit(&content, dialect) {
+        Ok(v) => v,
         Err(err) => {
             eprint!("{}", format_runtime_err(Some(filename), &lines, &err, 1));
-            1
+            return 1;
         }
-    }
-}
-
-fn execute_block(
-    state: &mut State,
-    prog: &mut Program,
-    scope: &mut HashMap<Token, Variable>,
-    main_stmts: &mut Vec<Statement>,
-    last_ptr: &mut usize,
-    exec_ptr: &mut usize,
-) -> Result<(), Error> {
-    // try to parse node into statements
-    let node = parse_node(state, "")?;
-    // validate all statements, adding function, denying return
-    for stmt in node.stmts {
-        if let Statement::Func {
-            name,
-            params,
-            child,
-            line,
-        } = stmt
-        {
-            if is_reserved_kw(&name.value) || prog.funcs.contains_key(&name) {
-                return Err(Error::DuplicateToken {
-                    line,
-                    value: name.value,
-                });
+    };
+    match output {
+        Some(path) => {
+            if fs::write(path, res).is_err() {
+                eprintln!("nhotyp: fatal error: {}: cannot write file", path);
+                return 1;
             }
-            prog.funcs.insert(
-                Token {
-                    value: String::from(&name.value),
-                },
-                Function {
-                    params,
-                    root: child,
-                    line,
-                },
-            );
-        } else if let Statement::Ret { line, .. } = stmt {
-            return Err(Error::WildStatement { line });
-        } else {
-            main_stmts.push(stmt);
         }
+        None => print!("{}", res),
     }
-    // create instance
-    let mut new_scope = HashMap::new();
-    for key in scope.keys() {
-        new_scope.insert(key.clone(), scope[key].clone());
-    }
-    let mut instance = RunInstance {
-        prog: prog,
-        scope: new_scope,
-    };
-    // attempt execution
-    let mut new_exec_ptr = *exec_ptr;
-    while new_exec_ptr < main_stmts.len() {
-        let stmt = &main_stmts[new_exec_ptr];
-        exec_statement(&mut instance, stmt)?;
-        new_exec_ptr += 1;
-    }
-    // writeback state
-    for key in instance.scope.keys() {
-        scope.insert(key.clone(), instance.scope[key].clone());
-    }
-    *last_ptr = state.ptr - 1;
-    *exec_ptr = new_exec_ptr;
-    Ok(())
+    0
 }
 
-fn main_ii_show_copyright() -> () {
-    println!("Copyright (c) 2021 Geoffrey Tang");
-    println!("All lefts reversed.");
-    println!("");
-}
-
-fn main_ii_show_license() -> () {
-    println!("MIT License");
-    println!("");
-    println!("Copyright (c) 2021 Geoffrey Tang");
-    println!("");
-    println!("Permission is hereby granted, free of charge, to any person obtaining a copy");
-    println!("of this software and associated documentation files (the \"Software\"), to deal");
-    println!("in the Software without restriction, including without limitation the rights");
-    println!("to use, copy, modify, merge, publish, distribute, sublicense, and/or sell");
-    println!("copies of the Software, and to permit persons to whom the Software is");
-    println!("furnished to do so, subject to the following conditions:");
-    println!("");
-    println!("The above copyright notice and this permission notice shall be included in all");
-    println!("copies or substantial portions of the Software.");
-    println!("");
-    println!("THE SOFTWARE IS PROVIDED \"AS IS\", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR");
-    println!("IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,");
-    println!("FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE");
-    println!("AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER");
-    println!("LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,");
-    println!("OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE");
-    println!("SOFTWARE.");
-    println!("");
+fn main_dap(args: &[String]) -> i32 {
+    if let Some(arg) = args.first() {
+        eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+        return 1;
+    }
+    // the protocol owns stdout, so errors may only go to stderr
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    match dap::serve(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("nhotyp: fatal error: {}", err);
+            1
+        }
+    }
 }
 
-fn main_interactive_interpreter() -> () {
-    // prepare interactive parsing
-    // first empty line is magic, used to avoid -1 pointers
-    // output debug messages need to be checked for sanity (line + 1)
-    let mut lines: Vec<String> = vec![String::default()];
-    let mut state = State {
-        lines: &mut lines,
-        ptr: 0,
-    };
-    // prepare execution unit (this is modifed on interaction)
-    let mut prog = Program {
-        funcs: HashMap::new(),
+fn main_run(argv: &[String]) {
+    // separate options from positional arguments
+    // decorations only make sense when someone is typing along
+    let mut opts = Options {
+        dialect: Dialect::Standard,
+        raw: !std::io::stdin().is_terminal(),
+        debug: false,
+        trace: false,
+        trace_file: None,
+        profile: false,
+        profile_file: None,
+        input: None,
     };
-    let mut main_stmts = vec![];
-    let mut scope = HashMap::new();
-    // the next statement to execute exec_ptr[..]
-    let mut exec_ptr = 0;
-    // the last validated lines[..]
-    let mut last_ptr = 0; 
-    // start parsing
-    let mut in_block = false;
-    loop {
-        // read input if possible
-        let mut inp_line = String::new();
-        print!("{}", if !in_block { ">>> " } else { "... " });
-        std::io::stdout().flush().expect("unable to flush stdout");
-        // reached EOF, gracefully exit
-        if let Err(_) = std::io::stdin().read_line(&mut inp_line) {
-            break;
-        }
-        inp_line = String::from(inp_line.trim());
-        // not something that we should consider
-        if inp_line.is_empty() {
-            continue;
-        }
-        // additional information
-        if inp_line == "copyright" {
-            main_ii_show_copyright();
-            continue;
-        } else if inp_line == "license" {
-            main_ii_show_license();
-            continue;
-        }
-        // push and attempt to parse, check for errors
-        while state.ptr > 0 && state.ptr > state.lines.len() {
-            state.ptr -= 1;
-        }
-        state.lines.push(inp_line);
-        match execute_block(
-            &mut state,
-            &mut prog,
-            &mut scope,
-            &mut main_stmts,
-            &mut last_ptr,
-            &mut exec_ptr,
-        ) {
-            Ok(()) => {
-                in_block = false;
-            }
-            Err(Error::UnclosedBlock) => {
-                in_block = true;
-                state.ptr = last_ptr;
-                continue;
-            }
-            Err(err) => {
-                in_block = false;
-                print!("{}", format_runtime_err(None, state.lines, &err, 0));
-                while state.lines.len() > last_ptr + 1 {
-                    state.lines.pop();
+    let mut args = vec![];
+    let mut iter = argv.iter().cloned();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--extended" => opts.dialect = Dialect::Extended,
+            "--raw" => opts.raw = true,
+            "--pretty" => opts.raw = false,
+            "--debug" => opts.debug = true,
+            "--trace" => opts.trace = true,
+            "--trace-file" => match iter.next() {
+                Some(v) => opts.trace_file = Some(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --trace-file expects a file name");
+                    eprintln!("intepretation terminated.");
+                    std::process::exit(1);
                 }
-                while main_stmts.len() > exec_ptr {
-                    main_stmts.pop();
+            },
+            "--profile" => opts.profile = true,
+            "--profile-collapsed" => match iter.next() {
+                Some(v) => opts.profile_file = Some(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --profile-collapsed expects a file name");
+                    eprintln!("intepretation terminated.");
+                    std::process::exit(1);
                 }
-                state.ptr = last_ptr;
-                continue;
+            },
+            "--input" => match iter.next() {
+                Some(v) => opts.input = Some(v),
+                None => {
+                    eprintln!("nhotyp: fatal error: --input expects a file name");
+                    eprintln!("intepretation terminated.");
+                    std::process::exit(1);
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("nhotyp: fatal error: unrecognized option {:?}", arg);
+                eprintln!("intepretation terminated.");
+                std::process::exit(1);
             }
-        };
+            _ => args.push(arg),
+        }
     }
-    println!("\n");
-    return;
-}
-
-fn main() {
     // read program from file
-    let args: Vec<_> = env::args().collect();
-    if args.len() <= 1 {
+    if args.is_empty() {
         println!("Nhotyp 0.1.0 (default, nightly-latest)");
         println!("[rustc 1.50.0 (cb75ad5db 2021-02-10)] on linux");
         println!("Type \"copyright\" or \"license\" for more information.");
-        main_interactive_interpreter();
-    } else if args.len() == 2 {
-        std::process::exit(main_run_file(&args[1]));
+        main_interactive_interpreter(&opts);
+    } else if args.len() == 1 {
+        std::process::exit(main_run_file(&opts, &args[0]));
     } else {
         eprintln!("nhotyp: fatal error: too many arguments");
         eprintln!("intepretation terminated.");
-        return;
+    }
+}
+
+fn main() {
+    // subcommands take over argument parsing entirely
+    let args: Vec<_> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("fmt") => std::process::exit(main_fmt(&args[1..])),
+        Some("lint") => std::process::exit(main_lint(&args[1..])),
+        Some("lsp") => std::process::exit(main_lsp(&args[1..])),
+        Some("dap") => std::process::exit(main_dap(&args[1..])),
+        Some("emit-c") => std::process::exit(main_emit(&args[1..], |source, dialect| {
+            emit_c::emit_c(&load(source, dialect)?)
+        })),
+        Some("emit-python") => std::process::exit(main_emit(&args[1..], |source, dialect| {
+            emit_python::emit_python(&load(source, dialect)?)
+        })),
+        Some("from-infix") => std::process::exit(main_emit(&args[1..], infix::convert)),
+        Some("coverage") => std::process::exit(main_coverage(&args[1..])),
+        Some("test") => std::process::exit(main_test(&args[1..])),
+        Some("judge") => {
+            // deep recursion must hit the depth limit before the real stack ends
+            let args = args[1..].to_vec();
+            let judge = thread::Builder::new()
+                .stack_size(JUDGE_STACK_SIZE)
+                .spawn(move || main_judge(&args));
+            std::process::exit(judge.map_or(1, |v| v.join().unwrap_or(1)))
+        }
+        _ => main_run(&args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fmt_check_reports_through_exit_status() {
+        let dir = env::temp_dir().join(format!("nhotyp-fmt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let tidy = "function main as\n    return 0\nend function\n";
+        let messy = "function main as\nreturn  0\nend function\n";
+        fs::write(path("tidy.nh"), tidy).unwrap();
+        fs::write(path("messy.nh"), messy).unwrap();
+        fs::write(path("broken.nh"), "function main as\n    let x foo 5\n").unwrap();
+        let fmt = |args: &[&str]| main_fmt(&args.iter().map(|v| path(v)).collect::<Vec<_>>());
+        let check = |name: &str| main_fmt(&[String::from("--check"), path(name)]);
+        assert_eq!(check("tidy.nh"), 0);
+        assert_eq!(check("messy.nh"), 1);
+        assert_eq!(check("broken.nh"), 1);
+        assert_eq!(fs::read_to_string(path("messy.nh")).unwrap(), messy);
+        assert_eq!(fmt(&["tidy.nh", "messy.nh"]), 0);
+        assert_eq!(fs::read_to_string(path("messy.nh")).unwrap(), tidy);
+        assert_eq!(check("messy.nh"), 0);
+        assert_eq!(fmt(&["broken.nh"]), 1);
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,243 @@
+//! Profiling of statement counts and function timings.
+//!
+//! Every statement execution is counted per line, where a `while` counts once
+//! per evaluation of its condition. Functions are timed with wall clock time,
+//! split into self time and time spent in callees, and the same split per call
+//! stack yields collapsed stacks as understood by flame graph tools.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Frame, Hook, Statement};
+
+#[derive(Default)]
+struct FuncStats {
+    calls: u64,
+    total: Duration,
+    own: Duration,
+}
+
+struct Call {
+    start: Instant,
+    // time spent in callees so far
+    nested: Duration,
+}
+
+pub struct Profiler<'a> {
+    lines: &'a [String],
+    counts: HashMap<usize, u64>,
+    funcs: HashMap<String, FuncStats>,
+    // self time per call stack, names joined by `;`
+    stacks: HashMap<String, Duration>,
+    calls: Vec<Call>,
+    names: Vec<String>,
+}
+
+impl<'a> Profiler<'a> {
+    pub fn new(lines: &'a [String]) -> Self {
+        Self {
+            lines,
+            counts: HashMap::new(),
+            funcs: HashMap::new(),
+            stacks: HashMap::new(),
+            calls: vec![],
+            names: vec![],
+        }
+    }
+
+    fn leave(&mut self) {
+        let (call, name) = match (self.calls.pop(), self.names.last()) {
+            (Some(call), Some(name)) => (call, name.clone()),
+            _ => return,
+        };
+        let total = call.start.elapsed();
+        let own = total.saturating_sub(call.nested);
+        *self.stacks.entry(self.names.join(";")).or_default() += own;
+        self.names.pop();
+        // recursive calls are already covered by the outermost one
+        let outermost = !self.names.contains(&name);
+        let stats = self.funcs.entry(name).or_default();
+        if outermost {
+            stats.total += total;
+        }
+        stats.own += own;
+        if let Some(parent) = self.calls.last_mut() {
+            parent.nested += total;
+        }
+    }
+
+    /// Closes calls left open by a program aborted with an error.
+    pub fn finish(&mut self) {
+        while !self.calls.is_empty() {
+            self.leave();
+        }
+    }
+
+    /// Formats functions by self time and lines by execution count.
+    pub fn report(&self) -> String {
+        let ms = |v: Duration| v.as_secs_f64() * 1000.0;
+        let mut funcs: Vec<_> = self.funcs.iter().collect();
+        funcs.sort_by(|a, b| b.1.own.cmp(&a.1.own).then(a.0.cmp(b.0)));
+        let mut res = format!(
+            "{:<24} {:>10} {:>12} {:>12}\n",
+            "function", "calls", "total ms", "self ms"
+        );
+        for (name, stats) in funcs {
+            res += &format!(
+                "{:<24} {:>10} {:>12.3} {:>12.3}\n",
+                name,
+                stats.calls,
+                ms(stats.total),
+                ms(stats.own)
+            );
+        }
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        res += &format!("\n{:>6} {:>10}  {}\n", "line", "count", "source");
+        for (line, count) in counts {
+            let source = self.lines.get(*line).map_or("", |v| v.trim());
+            res += &format!("{:>6} {:>10}  {}\n", line + 1, count, source);
+        }
+        res
+    }
+
+    /// Formats self time per call stack in microseconds, one stack per line.
+    pub fn collapsed(&self) -> String {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        stacks
+            .iter()
+            .map(|(k, v)| format!("{} {}\n", k, v.as_micros()))
+            .collect()
+    }
+}
+
+impl Hook for Profiler<'_> {
+    fn on_statement(&mut self, _frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        // loops are counted per condition instead
+        if let Statement::Loop { .. } = stmt {
+            return Ok(());
+        }
+        *self.counts.entry(stmt.line()).or_default() += 1;
+        Ok(())
+    }
+
+    fn on_condition(
+        &mut self,
+        _frames: &[Frame],
+        stmt: &Statement,
+        _value: i64,
+    ) -> Result<(), Error> {
+        if let Statement::Loop { line, .. } = stmt {
+            *self.counts.entry(*line).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        if let Some(frame) = frames.last() {
+            self.funcs.entry(frame.name.clone()).or_default().calls += 1;
+            self.names.push(frame.name.clone());
+            self.calls.push(Call {
+                start: Instant::now(),
+                nested: Duration::ZERO,
+            });
+        }
+        Ok(())
+    }
+
+    fn on_return(&mut self, _frames: &[Frame], _value: i64) -> Result<(), Error> {
+        self.leave();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{split_lines, BufferIo, Program};
+
+    const SOURCE: &str = "function fact n as
+    let res = 1
+    if > n 1 then
+        let res = * n fact - n 1
+    end if
+    return res
+end function
+function main as
+    let i = 0
+    while < i 3 do
+        let i = + i 1
+    end while
+    return fact 5
+end function
+";
+
+    fn profile(lines: &[String]) -> Profiler<'_> {
+        let mut prog = Program::new();
+        prog.load(SOURCE).unwrap();
+        let mut profiler = Profiler::new(lines);
+        let res = prog.execute_hooked(&mut BufferIo::new(""), &mut profiler);
+        assert_eq!(res, Ok(120));
+        profiler
+    }
+
+    #[test]
+    fn counts_statements_per_line() {
+        let lines = split_lines(SOURCE);
+        let profiler = profile(&lines);
+        let mut counts: Vec<_> = profiler.counts.iter().map(|(k, v)| (*k, *v)).collect();
+        counts.sort_unstable();
+        // the loop condition is evaluated once more than its body runs
+        assert_eq!(
+            counts,
+            [
+                (1, 5),
+                (2, 5),
+                (3, 4),
+                (5, 5),
+                (8, 1),
+                (9, 4),
+                (10, 3),
+                (12, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_recursive_calls_once_in_total() {
+        let lines = split_lines(SOURCE);
+        let profiler = profile(&lines);
+        let (fact, main) = (&profiler.funcs["fact"], &profiler.funcs["main"]);
+        assert_eq!((fact.calls, main.calls), (5, 1));
+        // nested calls lie within the outermost one, which lies within main
+        assert!(fact.total <= main.total);
+        assert!(fact.own <= fact.total);
+        assert_eq!(main.own + fact.total, main.total);
+    }
+
+    #[test]
+    fn collapses_stacks_by_call_path() {
+        let lines = split_lines(SOURCE);
+        let profiler = profile(&lines);
+        let collapsed = profiler.collapsed();
+        let stacks: Vec<_> = collapsed
+            .lines()
+            .map(|v| v.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            [
+                "main",
+                "main;fact",
+                "main;fact;fact",
+                "main;fact;fact;fact",
+                "main;fact;fact;fact;fact",
+                "main;fact;fact;fact;fact;fact",
+            ]
+        );
+        assert!(profiler
+            .report()
+            .contains("     4          4  let res = * n fact - n 1\n"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Execution tracing, logging every statement as it runs.
+//!
+//! Each line of the trace starts with the source line number and is indented
+//! by call depth. Conditions are shown with the value they evaluated to, and
+//! assignments with the value they replaced.
+
+use std::io::Write;
+
+use crate::{Error, Frame, Hook, Statement};
+
+pub struct Tracer<'a> {
+    lines: &'a [String],
+    output: &'a mut dyn Write,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new(lines: &'a [String], output: &'a mut dyn Write) -> Self {
+        Self { lines, output }
+    }
+
+    fn log(&mut self, frames: &[Frame], line: usize, note: &str) {
+        let indent = "  ".repeat(frames.len().saturating_sub(1));
+        let source = self.lines.get(line).map_or("", |v| v.trim());
+        // tracing is best effort and never aborts the program
+        let _ = match note.is_empty() {
+            true => writeln!(self.output, "{}{}: {}", indent, line + 1, source),
+            false => writeln!(
+                self.output,
+                "{}{}: {}  [{}]",
+                indent,
+                line + 1,
+                source,
+                note
+            ),
+        };
+    }
+}
+
+impl Hook for Tracer<'_> {
+    fn on_statement(&mut self, frames: &[Frame], stmt: &Statement) -> Result<(), Error> {
+        // these are logged once their outcome is known
+        if let Statement::Assign { .. } | Statement::Cond { .. } | Statement::Loop { .. } = stmt {
+            return Ok(());
+        }
+        self.log(frames, stmt.line(), "");
+        Ok(())
+    }
+
+    fn on_condition(
+        &mut self,
+        frames: &[Frame],
+        stmt: &Statement,
+        value: i64,
+    ) -> Result<(), Error> {
+        self.log(frames, stmt.line(), &format!("condition = {}", value));
+        Ok(())
+    }
+
+    fn on_assign(
+        &mut self,
+        frames: &[Frame],
+        var: &str,
+        old: Option<i64>,
+        new: i64,
+    ) -> Result<(), Error> {
+        let note = match old {
+            Some(old) => format!("{}: {} -> {}", var, old, new),
+            None => format!("{} = {}", var, new),
+        };
+        let line = frames.last().map_or(0, |v| v.line);
+        self.log(frames, line, &note);
+        Ok(())
+    }
+
+    fn on_call(&mut self, frames: &[Frame]) -> Result<(), Error> {
+        if let Some(frame) = frames.last() {
+            let args: Vec<_> = frame
+                .variables()
+                .iter()
+                .map(|(k, v)| format!("{} = {}", k, v))
+                .collect();
+            let indent = "  ".repeat(frames.len() - 1);
+            let _ = writeln!(
+                self.output,
+                "{}call {}({})",
+                indent,
+                frame.name,
+                args.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    fn on_return(&mut self, frames: &[Frame], value: i64) -> Result<(), Error> {
+        if let Some(frame) = frames.last() {
+            let indent = "  ".repeat(frames.len() - 1);
+            let _ = writeln!(self.output, "{}{} returned {}", indent, frame.name, value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{split_lines, BufferIo, Program};
+
+    const SOURCE: &str = "function f x as
+    return + x 1
+end function
+function main as
+    let a = 1
+    if a then
+        let a = f a
+    end if
+    while < a 3 do
+        let a = + a 1
+    end while
+    print a
+    return 0
+end function
+";
+
+    #[test]
+    fn logs_statements_by_call_depth() {
+        let mut prog = Program::new();
+        prog.set_raw(true);
+        prog.load(SOURCE).unwrap();
+        let lines = split_lines(SOURCE);
+        let mut output = vec![];
+        let mut tracer = Tracer::new(&lines, &mut output);
+        let mut io = BufferIo::new("");
+        assert_eq!(prog.execute_hooked(&mut io, &mut tracer), Ok(0));
+        assert_eq!(io.stdout, "3\n");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "call main()\n",
+                "5: let a = 1  [a = 1]\n",
+                "6: if a then  [condition = 1]\n",
+                "  call f(x = 1)\n",
+                "  2: return + x 1\n",
+                "  f returned 2\n",
+                "7: let a = f a  [a: 1 -> 2]\n",
+                "9: while < a 3 do  [condition = 1]\n",
+                "10: let a = + a 1  [a: 2 -> 3]\n",
+                "9: while < a 3 do  [condition = 0]\n",
+                "12: print a\n",
+                "13: return 0\n",
+                "main returned 0\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Static resolution of programs, shared by the translators to other
+//! languages.
+//!
+//! The interpreter resolves every token of an expression while running it.
+//! Translation needs to do so up front: a token is a literal, a local
+//! variable of the function it appears in, a built-in operator or a call, in
+//! the same order of precedence as at runtime. Local variables are the
+//! parameters of a function along with every name it assigns to.
+
+use std::convert::TryFrom;
+
+use crate::{find_builtin, Dialect, Error, Expr, Function, Node, Program, Statement};
+use crate::{Token, VARIABLE_LIMIT};
+
+pub(crate) enum Term {
+    Const(i64),
+    Var(String),
+    /// Built-in operators, `scan` and `eof` included.
+    Builtin(&'static str, Vec<Term>),
+    Call(String, Vec<Term>),
+}
+
+impl Term {
+    /// Whether evaluating the term reads input or runs user code, which
+    /// makes its order of evaluation relevant.
+    pub(crate) fn is_impure(&self) -> bool {
+        match self {
+            Self::Const(_) | Self::Var(_) => false,
+            Self::Builtin("scan", _) | Self::Builtin("eof", _) | Self::Call(..) => true,
+            Self::Builtin(_, args) => args.iter().any(Term::is_impure),
+        }
+    }
+}
+
+/// Functions of a program in order of definition.
+pub(crate) fn functions(prog: &Program) -> Vec<(&str, &Function)> {
+    let mut res: Vec<_> = prog
+        .funcs
+        .iter()
+        .map(|(k, v)| (k.value.as_str(), v))
+        .collect();
+    res.sort_by_key(|(_, v)| v.line);
+    res
+}
+
+/// Checks for the `main` function every translated program starts with.
+pub(crate) fn check_main(prog: &Program) -> Result<(), Error> {
+    let main = Token {
+        value: String::from("main"),
+    };
+    match prog.funcs.get(&main) {
+        Some(func) if func.params.is_empty() => Ok(()),
+        Some(_) => Err(Error::BadArguments {
+            line: 0,
+            value: main.value,
+        }),
+        None => Err(Error::UndeclaredToken {
+            line: 0,
+            value: main.value,
+        }),
+    }
+}
+
+/// Names assigned by a function, excluding its parameters, in order of
+/// their first assignment.
+pub(crate) fn locals(func: &Function) -> Vec<&str> {
+    fn collect<'a>(node: &'a Node, res: &mut Vec<&'a str>) {
+        for stmt in &node.stmts {
+            match stmt {
+                Statement::Assign { var, .. } if !res.contains(&var.value.as_str()) => {
+                    res.push(&var.value)
+                }
+                Statement::Cond { child, .. } | Statement::Loop { child, .. } => {
+                    collect(child, res)
+                }
+                _ => (),
+            }
+        }
+    }
+    let mut res = vec![];
+    collect(&func.root, &mut res);
+    res.retain(|v| !func.params.iter().any(|p| p.value == *v));
+    res
+}
+
+/// Checks that every variable is assigned before it is read, whichever way the
+/// program runs. Assignments within a block count only until its end, as the
+/// block may not run at all.
+fn check_reads<'a>(
+    locals: &[&str],
+    node: &'a Node,
+    assigned: &mut Vec<&'a str>,
+) -> Result<(), Error> {
+    for stmt in &node.stmts {
+        let (expr, line) = match stmt {
+            Statement::Assign { expr, line, .. }
+            | Statement::Cond { expr, line, .. }
+            | Statement::Loop { expr, line, .. }
+            | Statement::Print { expr, line }
+            | Statement::Eprint { expr, line }
+            | Statement::Assert { expr, line }
+            | Statement::Ret { expr, line } => (expr, *line),
+            Statement::Func { .. } | Statement::Test { .. } => continue,
+        };
+        for token in &expr.tokens {
+            let name = token.value.as_str();
+            if find_builtin(name).is_none() && locals.contains(&name) && !assigned.contains(&name) {
+                return Err(Error::UndeclaredToken {
+                    line,
+                    value: String::from(name),
+                });
+            }
+        }
+        match stmt {
+            Statement::Assign { var, .. } => assigned.push(&var.value),
+            Statement::Cond { child, .. } | Statement::Loop { child, .. } => {
+                check_reads(locals, child, &mut assigned.clone())?
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a function ends in its only `return`, defines nothing and
+/// reads no variable before assigning it, which the interpreter would only
+/// find out when running it.
+pub(crate) fn check_function(prog: &Program, func: &Function) -> Result<(), Error> {
+    fn check(prog: &Program, stmts: &[Statement]) -> Result<(), Error> {
+        for stmt in stmts {
+            match stmt {
+                Statement::Assign { var, line, .. } if prog.contains(var) => {
+                    return Err(Error::DuplicateToken {
+                        line: *line,
+                        value: String::from(&var.value),
+                    })
+                }
+                Statement::Cond { child, .. } | Statement::Loop { child, .. } => {
+                    check(prog, &child.stmts)?
+                }
+                Statement::Ret { line, .. } => return Err(Error::MisplacedRet { line: *line }),
+                Statement::Func { line, .. } => return Err(Error::WildFunction { line: *line }),
+                Statement::Test { line, .. } => return Err(Error::WildTest { line: *line }),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+    for param in &func.params {
+        if prog.contains(param) {
+            return Err(Error::DuplicateToken {
+                line: func.line,
+                value: String::from(&param.value),
+            });
+        }
+    }
+    match func.root.stmts.split_last() {
+        Some((Statement::Ret { .. }, rest)) => check(prog, rest)?,
+        _ => return Err(Error::MisplacedRet { line: func.line }),
+    }
+    let mut assigned: Vec<_> = func.params.iter().map(|v| v.value.as_str()).collect();
+    check_reads(&locals(func), &func.root, &mut assigned)
+}
+
+/// Resolves names of one function.
+pub(crate) struct Resolver<'a> {
+    prog: &'a Program,
+    vars: Vec<&'a str>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(prog: &'a Program, func: &'a Function) -> Self {
+        let mut vars: Vec<_> = func.params.iter().map(|v| v.value.as_str()).collect();
+        vars.extend(locals(func));
+        Self { prog, vars }
+    }
+
+    pub(crate) fn is_var(&self, name: &str) -> bool {
+        self.vars.contains(&name)
+    }
+
+    fn term(&self, tokens: &[Token], ptr: &mut usize, line: usize) -> Result<Term, Error> {
+        let token = match tokens.get(*ptr) {
+            Some(v) => v.value.as_str(),
+            None => return Err(Error::BadExpression { line }),
+        };
+        *ptr += 1;
+        let (op, cnt) = match find_builtin(token) {
+            Some(builtin) => (Some(builtin.name), builtin.arity),
+            None => {
+                if let Ok(v) = token.parse::<i128>() {
+                    return literal(v, line);
+                }
+                if self.is_var(token) {
+                    return Ok(Term::Var(String::from(token)));
+                }
+                let key = Token {
+                    value: String::from(token),
+                };
+                match self.prog.funcs.get(&key) {
+                    Some(func) => (None, func.params.len()),
+                    None => {
+                        return Err(Error::UndeclaredToken {
+                            line,
+                            value: String::from(token),
+                        })
+                    }
+                }
+            }
+        };
+        let mut args = vec![];
+        for _ in 0..cnt {
+            args.push(self.term(tokens, ptr, line)?);
+        }
+        Ok(match op {
+            Some(op) => Term::Builtin(op, args),
+            None => Term::Call(String::from(token), args),
+        })
+    }
+
+    /// Resolves an expression, which must consist of a single term.
+    pub(crate) fn expr(&self, expr: &Expr, line: usize) -> Result<Term, Error> {
+        let mut ptr = 0;
+        let res = self.term(&expr.tokens, &mut ptr, line)?;
+        if ptr < expr.tokens.len() {
+            return Err(Error::BadExpression { line });
+        }
+        Ok(res)
+    }
+
+    /// Resolves the values of a `print` statement, which are variables only
+    /// in the standard dialect.
+    pub(crate) fn print_args(&self, expr: &Expr, line: usize) -> Result<Vec<Term>, Error> {
+        let mut res = vec![];
+        let mut ptr = 0;
+        while ptr < expr.tokens.len() {
+            let token = &expr.tokens[ptr].value;
+            if self.prog.dialect == Dialect::Standard && !self.is_var(token) {
+                return Err(Error::UndeclaredToken {
+                    line,
+                    value: String::from(token),
+                });
+            }
+            res.push(self.term(&expr.tokens, &mut ptr, line)?);
+        }
+        Ok(res)
+    }
+}
+
+/// Literals wrap around just like computed values.
+fn literal(value: i128, line: usize) -> Result<Term, Error> {
+    let value = match value > 0 {
+        true => value & (VARIABLE_LIMIT - 1),
+        false => value,
+    };
+    match i64::try_from(value) {
+        Ok(v) => Ok(Term::Const(v)),
+        Err(_) => Err(Error::BadExpression { line }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(body: &str) -> Result<(), Error> {
+        let mut prog = Program::new();
+        prog.load(&format!(
+            "function main n as\n{}    return 0\nend function\n",
+            body
+        ))
+        .unwrap();
+        let (_, func) = functions(&prog)[0];
+        check_function(&prog, func)
+    }
+
+    #[test]
+    fn rejects_reads_before_assignments() {
+        assert!(check("    let a = n\n    let b = + a 1\n").is_ok());
+        assert!(
+            check("    let a = 0\n    while < a n do\n        let a = + a 1\n    end while\n")
+                .is_ok()
+        );
+        assert_eq!(
+            check("    let a = + a 1\n"),
+            Err(Error::UndeclaredToken {
+                line: 1,
+                value: String::from("a"),
+            })
+        );
+        // the block may not run at all
+        assert_eq!(
+            check("    if n then\n        let a = 1\n    end if\n    print a\n"),
+            Err(Error::UndeclaredToken {
+                line: 4,
+                value: String::from("a"),
+            })
+        );
+        assert_eq!(
+            check("    while not n do\n        let n = b\n        let b = 1\n    end while\n"),
+            Err(Error::UndeclaredToken {
+                line: 2,
+                value: String::from("b"),
+            })
+        );
+    }
+}